@@ -0,0 +1,161 @@
+use {
+    crate::{
+        auth,
+        privacy::{anonymize_ip, redact_path},
+        requestid::RequestId,
+    },
+    actix_web::{dev::ServiceRequest, http::header},
+    chrono::{DateTime, Local},
+    serde_json::json,
+    std::{io::Write, sync::mpsc, thread, time::Duration},
+};
+
+#[allow(dead_code)] // The format in use is picked in config.rs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessLogFormat {
+    /// Apache Combined Log Format with the request duration in seconds appended
+    Combined,
+    /// One JSON object per line
+    Json,
+}
+
+pub struct Entry {
+    pub client: String,
+    pub time: DateTime<Local>,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    pub size: Option<u64>,
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: String,
+}
+
+impl Entry {
+    /// Collect the request side of an entry, the response side is filled in once it is sent
+    pub fn from_request(request: &ServiceRequest, request_id: &RequestId) -> Self {
+        let header = |name| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(String::from)
+        };
+        Self {
+            client: anonymize_ip(&auth::client(request.request())),
+            time: Local::now(),
+            method: request.method().to_string(),
+            path: redact_path(&request.uri().to_string()).to_string(),
+            protocol: format!("{:?}", request.version()),
+            status: 0,
+            size: None,
+            duration: Duration::ZERO,
+            referer: header(header::REFERER),
+            user_agent: header(header::USER_AGENT),
+            request_id: request_id.to_string(),
+        }
+    }
+
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Combined => format!(
+                "{} - - [{}] \"{} {} {}\" {} {} \"{}\" \"{}\" {:.6} {}",
+                self.client,
+                self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                self.method,
+                escape(&self.path),
+                self.protocol,
+                self.status,
+                self.size
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                escape(self.referer.as_deref().unwrap_or("-")),
+                escape(self.user_agent.as_deref().unwrap_or("-")),
+                self.duration.as_secs_f64(),
+                self.request_id,
+            ),
+            AccessLogFormat::Json => json!({
+                "time": self.time.to_rfc3339(),
+                "client": self.client,
+                "method": self.method,
+                "path": self.path,
+                "protocol": self.protocol,
+                "status": self.status,
+                "size": self.size,
+                "duration": self.duration.as_secs_f64(),
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "request_id": self.request_id,
+            })
+            .to_string(),
+        }
+    }
+}
+
+fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Handle to the access log thread, entries are written without any logger prefix so tools like
+/// goaccess can parse the file
+#[derive(Clone)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    sender: mpsc::Sender<String>,
+}
+
+impl AccessLog {
+    pub fn spawn<W: 'static + Write + Send>(format: AccessLogFormat, mut writer: W) -> Self {
+        let (sender, receiver) = mpsc::channel::<String>();
+        thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                for line in receiver {
+                    let _ = writeln!(writer, "{}", line);
+                }
+            })
+            .expect("Unable to start the access log thread");
+        Self { format, sender }
+    }
+
+    pub fn log(&self, entry: &Entry) {
+        let _ = self.sender.send(entry.format(self.format));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    #[test]
+    fn formats() {
+        let entry = Entry {
+            client: "10.0.0.1".to_string(),
+            time: Local.with_ymd_and_hms(2023, 7, 4, 12, 30, 0).unwrap(),
+            method: "GET".to_string(),
+            path: "/list?a=\"b\"".to_string(),
+            protocol: "HTTP/1.1".to_string(),
+            status: 200,
+            size: Some(1234),
+            duration: Duration::from_millis(15),
+            referer: None,
+            user_agent: Some("curl/8.0".to_string()),
+            request_id: "4f2a".to_string(),
+        };
+
+        let combined = entry.format(AccessLogFormat::Combined);
+        assert!(combined.starts_with("10.0.0.1 - - [04/Jul/2023:12:30:00 "));
+        assert!(combined.ends_with(
+            "\"GET /list?a=\\\"b\\\" HTTP/1.1\" 200 1234 \"-\" \"curl/8.0\" 0.015000 4f2a"
+        ));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&entry.format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(json["status"], 200);
+        assert_eq!(json["path"], "/list?a=\"b\"");
+        assert_eq!(json["referer"], serde_json::Value::Null);
+        assert_eq!(json["request_id"], "4f2a");
+    }
+}
@@ -0,0 +1,337 @@
+use {
+    crate::{
+        announcements::{self, Severity, Target},
+        audit, auth, delete_playlist, privacy, request_rescan,
+        requestid::RequestId,
+        save_playlist, set_source, set_visibility, tokens,
+        users::Actor,
+        State, Visibility,
+    },
+    actix_web::{
+        http::{header, StatusCode},
+        web, HttpRequest, HttpResponse, Responder,
+    },
+    chrono::{DateTime, Utc},
+    fast_logger::{error, warn},
+    serde_derive::{Deserialize, Serialize},
+};
+
+/// `/api/admin`, authenticated by `Authorization: Bearer <token>` with the scope of each route
+pub fn configure(config: &mut web::ServiceConfig) {
+    config
+        .route("/announcement", web::post().to(announce))
+        .route("/announcement", web::delete().to(denounce_all))
+        .route("/announcement/{id}", web::delete().to(denounce))
+        .route("/rescan", web::post().to(rescan))
+        .route("/videos/{name}/source", web::post().to(source))
+        .route("/videos/{name}/source", web::delete().to(unsource))
+        .route("/videos/{name}/visibility", web::post().to(visibility))
+        .route("/playlists/{id}", web::post().to(playlist))
+        .route("/playlists/{id}", web::delete().to(unplaylist));
+}
+
+#[derive(Serialize)]
+struct Reply {
+    message: String,
+}
+
+#[derive(Serialize)]
+struct Failure {
+    error: String,
+}
+
+fn failure(status: StatusCode, error: impl Into<String>) -> HttpResponse {
+    HttpResponse::build(status).json(Failure {
+        error: error.into(),
+    })
+}
+
+fn reply(result: Result<String, String>) -> HttpResponse {
+    match result {
+        Ok(message) => HttpResponse::Ok().json(Reply { message }),
+        Err(error) => failure(StatusCode::BAD_REQUEST, error),
+    }
+}
+
+/// Reply with the outcome of a change and add it to the audit log
+fn done(
+    state: &State,
+    request: &HttpRequest,
+    actor: &Actor,
+    arguments: &str,
+    result: Result<String, String>,
+) -> HttpResponse {
+    let entry = audit::Entry::new(request, &actor.user, &audit::route(request), arguments);
+    audit::record(state, entry.result(&result));
+    reply(result)
+}
+
+/// Make a change on the blocking thread pool, changes write files and some wait for the updater
+async fn change(
+    state: &State,
+    actor: &Actor,
+    change: impl FnOnce(&State, &Actor) -> Result<String, String> + Send + 'static,
+) -> Result<String, String> {
+    let (state, actor) = (state.clone(), actor.clone());
+    web::block(move || change(&state, &actor))
+        .await
+        .unwrap_or_else(|err| Err(err.to_string()))
+}
+
+/// The body of a request as it is written to the audit log
+fn arguments(body: &impl serde::Serialize) -> String {
+    serde_json::to_string(body).unwrap_or_default()
+}
+
+/// The actor a valid token with `scope` acts as, named `token:<name>` in the logs. Wrong tokens
+/// are throttled per client, apart from wrong passwords, so a broken script can not lock the
+/// admins out of /login.
+fn authenticate(state: &State, request: &HttpRequest, scope: &str) -> Result<Actor, HttpResponse> {
+    let request_id = RequestId::of(request);
+    let client = auth::client(request);
+    if let Err(wait) = state.token_throttle.check(&client) {
+        return Err(failure(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "Too many wrong tokens, try again in {} seconds",
+                wait.as_secs() + 1
+            ),
+        ));
+    }
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .trim();
+    let token = match tokens::verify(bearer) {
+        Ok(Some(token)) => token,
+        Ok(None) => {
+            let locked_out = state.token_throttle.failed(&client);
+            let client = privacy::anonymize_ip(&client);
+            warn!(state.lgr_important.borrow(), "Wrong API token"; "client" => client, "locked_out" => locked_out, "request_id" => request_id);
+            let entry = audit::Entry::new(request, "", &audit::route(request), "");
+            audit::record(state, entry.denied("Missing or wrong token"));
+            return Err(failure(StatusCode::UNAUTHORIZED, "Missing or wrong token"));
+        }
+        Err(err) => {
+            let message = err.clone();
+            error!(state.lgr_important.borrow(), "Unable to check the API token"; "error" => err, "request_id" => request_id);
+            return Err(failure(StatusCode::INTERNAL_SERVER_ERROR, message));
+        }
+    };
+    state.token_throttle.succeeded(&client);
+    let user = format!("token:{}", token.name);
+    if !token.allows(scope) {
+        let message = format!("The token does not have the {} scope", scope);
+        let entry = audit::Entry::new(request, &user, &audit::route(request), "");
+        audit::record(state, entry.denied(&message));
+        return Err(failure(StatusCode::FORBIDDEN, message));
+    }
+    Ok(Actor { user, request_id })
+}
+
+#[derive(Deserialize, Serialize)]
+struct Announcement {
+    markdown: String,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default)]
+    target: Target,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+async fn announce(
+    state: web::Data<State>,
+    body: web::Json<Announcement>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "announcement") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let body = body.into_inner();
+    let result = match announcements::Announcement::new(
+        &body.markdown,
+        body.severity,
+        body.target,
+        body.start,
+        body.end,
+    ) {
+        Ok(announcement) => {
+            change(&state, &actor, move |state, actor| {
+                announcements::add(state, announcement, actor)
+            })
+            .await
+        }
+        Err(err) => Err(err),
+    };
+    done(&state, &request, &actor, &arguments(&body), result)
+}
+
+/// Removes one announcement, an id that is not a number is a 404
+async fn denounce(
+    state: web::Data<State>,
+    id: web::Path<u64>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "announcement") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let id = id.into_inner();
+    let result = change(&state, &actor, move |state, actor| {
+        announcements::remove(state, Some(id), actor)
+    })
+    .await;
+    done(&state, &request, &actor, "", result)
+}
+
+async fn denounce_all(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    let actor = match authenticate(&state, &request, "announcement") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let result = change(&state, &actor, |state, actor| {
+        announcements::remove(state, None, actor)
+    })
+    .await;
+    done(&state, &request, &actor, "", result)
+}
+
+async fn rescan(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    let actor = match authenticate(&state, &request, "rescan") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let updates = state.updates.clone();
+    let result = web::block(move || request_rescan(&updates)).await;
+    let summary = match &result {
+        Ok(Ok(rescan)) => Ok(rescan.summary()),
+        Ok(Err(err)) => Err(err.clone()),
+        Err(err) => Err(err.to_string()),
+    };
+    let entry = audit::Entry::new(&request, &actor.user, &audit::route(&request), "");
+    audit::record(&state, entry.result(&summary));
+    match result {
+        Ok(Ok(result)) => HttpResponse::Ok().json(result),
+        Ok(Err(err)) => failure(StatusCode::SERVICE_UNAVAILABLE, err),
+        Err(err) => failure(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Source {
+    source: String,
+}
+
+async fn source(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    body: web::Json<Source>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "source") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let source = body.source.trim().to_string();
+    let result = if source.is_empty() {
+        Err("The source is empty".to_string())
+    } else {
+        let name = name.into_inner();
+        change(&state, &actor, move |state, actor| {
+            set_source(state, &name, Some(source), actor)
+        })
+        .await
+    };
+    done(&state, &request, &actor, &arguments(&*body), result)
+}
+
+async fn unsource(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "source") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let name = name.into_inner();
+    let result = change(&state, &actor, move |state, actor| {
+        set_source(state, &name, None, actor)
+    })
+    .await;
+    done(&state, &request, &actor, "", result)
+}
+
+#[derive(Deserialize, Serialize)]
+struct VisibilityBody {
+    visibility: String,
+}
+
+async fn visibility(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    body: web::Json<VisibilityBody>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "visibility") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let result = match Visibility::parse(&body.visibility) {
+        Some(visibility) => {
+            let name = name.into_inner();
+            change(&state, &actor, move |state, actor| {
+                set_visibility(state, &name, visibility, actor)
+            })
+            .await
+        }
+        None => Err("The visibility is public, unlisted or hidden".to_string()),
+    };
+    done(&state, &request, &actor, &arguments(&*body), result)
+}
+
+#[derive(Deserialize, Serialize)]
+struct Playlist {
+    videos: Vec<String>,
+}
+
+async fn playlist(
+    state: web::Data<State>,
+    id: web::Path<String>,
+    body: web::Json<Playlist>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "playlist") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let arguments = arguments(&*body);
+    let (id, videos) = (id.into_inner(), body.into_inner().videos);
+    let result = change(&state, &actor, move |state, actor| {
+        save_playlist(state, &id, videos, actor)
+    })
+    .await;
+    done(&state, &request, &actor, &arguments, result)
+}
+
+async fn unplaylist(
+    state: web::Data<State>,
+    id: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let actor = match authenticate(&state, &request, "playlist") {
+        Ok(actor) => actor,
+        Err(response) => return response,
+    };
+    let id = id.into_inner();
+    let result = change(&state, &actor, move |state, actor| {
+        delete_playlist(state, &id, actor).map(|()| "Playlist removed".to_string())
+    })
+    .await;
+    done(&state, &request, &actor, "", result)
+}
@@ -0,0 +1,339 @@
+use {
+    crate::{users::Actor, write_atomically, State},
+    chrono::{DateTime, NaiveDateTime, Utc},
+    fast_logger::{error, info, InDebug},
+    maud::{html, Markup, PreEscaped},
+    pulldown_cmark::{html::push_html, Parser},
+    serde_derive::{Deserialize, Serialize},
+    std::{fs, io, path::Path},
+};
+
+/// Kept outside of `files/` so scheduled announcements are not served before they start
+pub static ANNOUNCEMENTS_FILE: &str = "announcements.json";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    /// Also the CSS class of the announcement
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub fn parse(severity: &str) -> Result<Self, String> {
+        match severity {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "Unknown severity: {}, use info, warning or critical",
+                severity
+            )),
+        }
+    }
+}
+
+/// The pages an announcement is shown on
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// Every page, pages other than video pages and /list only show these
+    #[default]
+    All,
+    Video,
+    List,
+}
+
+impl Target {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Target::All => "all",
+            Target::Video => "video",
+            Target::List => "list",
+        }
+    }
+
+    pub fn parse(target: &str) -> Result<Self, String> {
+        match target {
+            "all" => Ok(Target::All),
+            "video" => Ok(Target::Video),
+            "list" => Ok(Target::List),
+            _ => Err(format!("Unknown page: {}, use all, video or list", target)),
+        }
+    }
+}
+
+/// Put into the list page where the announcements go, so they change without regenerating it
+pub static SLOT: &str = "<!--announcements-->";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Announcement {
+    #[serde(default)]
+    pub id: u64,
+    pub markdown: String,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default)]
+    pub target: Target,
+    /// Shown from this time on, right away when not set
+    #[serde(default)]
+    pub start: Option<DateTime<Utc>>,
+    /// Hidden from this time on, shown until removed when not set
+    #[serde(default)]
+    pub end: Option<DateTime<Utc>>,
+    /// Rendered once when added or loaded
+    #[serde(skip)]
+    html: String,
+}
+
+impl Announcement {
+    pub fn new(
+        markdown: &str,
+        severity: Severity,
+        target: Target,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> Result<Self, String> {
+        if markdown.trim().is_empty() {
+            return Err("The announcement is empty".to_string());
+        }
+        if let (Some(start), Some(end)) = (start, end) {
+            if end <= start {
+                return Err("The announcement ends before it starts".to_string());
+            }
+        }
+        Ok(Self {
+            id: 0,
+            markdown: markdown.trim().to_string(),
+            severity,
+            target,
+            start,
+            end,
+            html: render(markdown),
+        })
+    }
+
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.start.map_or(true, |x| x <= now) && !self.is_expired(now)
+    }
+
+    /// Whether it belongs on a page, which is `Target::All` for pages other than video pages and
+    /// /list
+    fn shows_on(&self, page: Target) -> bool {
+        self.target == Target::All || self.target == page
+    }
+
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.end.map_or(false, |x| x <= now)
+    }
+
+    fn describe(&self, now: DateTime<Utc>) -> String {
+        let status = if self.is_active(now) {
+            "active"
+        } else if self.is_expired(now) {
+            "expired"
+        } else {
+            "scheduled"
+        };
+        let time = |x: Option<DateTime<Utc>>| {
+            x.map(|x| x.format("%Y-%m-%dT%H:%M").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        format!(
+            "{} {} {} on {} from {} until {}: {}",
+            self.id,
+            self.severity.as_str(),
+            status,
+            self.target.as_str(),
+            time(self.start),
+            time(self.end),
+            self.markdown
+        )
+    }
+}
+
+/// Markdown to HTML, with scripts, styles, event handlers and other unsafe markup removed
+pub fn render(markdown: &str) -> String {
+    let mut html = String::new();
+    push_html(&mut html, Parser::new(markdown));
+    ammonia::clean(&html)
+}
+
+/// A time in RFC 3339 or `YYYY-MM-DDTHH:MM` in UTC
+pub fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|x| x.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M").map(|x| x.and_utc()))
+        .map_err(|_| format!("Not a time: {}, use YYYY-MM-DDTHH:MM in UTC", time))
+}
+
+/// Split the leading `severity=`, `on=`, `from=` and `until=` options off the text of `announce`
+pub fn parse_options(mut text: &str) -> Result<Announcement, String> {
+    let (mut severity, mut target, mut start, mut end) =
+        (Severity::default(), Target::default(), None, None);
+    loop {
+        text = text.trim_start();
+        let word = text.split_whitespace().next().unwrap_or_default();
+        match word.split_once('=') {
+            Some(("severity", value)) => severity = Severity::parse(value)?,
+            Some(("on", value)) => target = Target::parse(value)?,
+            Some(("from", value)) => start = Some(parse_time(value)?),
+            Some(("until", value)) => end = Some(parse_time(value)?),
+            _ => break,
+        }
+        text = &text[word.len()..];
+    }
+    Announcement::new(text, severity, target, start, end)
+}
+
+/// The announcements stored by `add` and `remove`, none when the file does not exist yet
+pub fn load() -> Result<Vec<Announcement>, String> {
+    let contents = match fs::read_to_string(ANNOUNCEMENTS_FILE) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(format!("Unable to read the announcements: {}", err)),
+    };
+    let mut announcements: Vec<Announcement> = serde_json::from_str(&contents)
+        .map_err(|err| format!("Unable to parse the announcements: {}", err))?;
+    for announcement in &mut announcements {
+        announcement.html = render(&announcement.markdown);
+    }
+    Ok(announcements)
+}
+
+fn save(state: &State, announcements: &[Announcement], actor: &Actor) -> Result<(), String> {
+    serde_json::to_string_pretty(announcements)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .and_then(|json| write_atomically(Path::new(ANNOUNCEMENTS_FILE), &json))
+        .map_err(|err| {
+            let message = format!("Unable to write the announcements: {}", err);
+            let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+            error!(state.lgr_important.borrow(), "Unable to write the announcements"; "error" => err, "user" => user, "request_id" => request_id);
+            message
+        })
+}
+
+/// Store a new announcement, expired ones are dropped at the same time
+pub fn add(state: &State, mut announcement: Announcement, actor: &Actor) -> Result<String, String> {
+    let mut announcements = state.announcements.write().unwrap();
+    let now = Utc::now();
+    announcement.id = announcements.iter().map(|x| x.id).max().unwrap_or(0) + 1;
+    let mut updated: Vec<Announcement> = announcements
+        .iter()
+        .filter(|x| !x.is_expired(now))
+        .cloned()
+        .collect();
+    updated.push(announcement.clone());
+    save(state, &updated, actor)?;
+    *announcements = updated;
+
+    let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+    info!(state.lgr_important.borrow(), "Announcement added"; "id" => announcement.id, "severity" => announcement.severity.as_str(), "target" => announcement.target.as_str(), "start" => InDebug(&announcement.start), "end" => InDebug(&announcement.end), "markdown" => announcement.markdown, "user" => user, "request_id" => request_id; clone announcement);
+    Ok(format!("Announcement {} added", announcement.id))
+}
+
+/// Remove one announcement, or all of them when `id` is `None`
+pub fn remove(state: &State, id: Option<u64>, actor: &Actor) -> Result<String, String> {
+    let mut announcements = state.announcements.write().unwrap();
+    let updated: Vec<Announcement> = announcements
+        .iter()
+        .filter(|x| id.map_or(false, |id| x.id != id))
+        .cloned()
+        .collect();
+    let removed = announcements.len() - updated.len();
+    if removed == 0 {
+        return Err(match id {
+            Some(id) => format!("No such announcement: {}", id),
+            None => "There are no announcements".to_string(),
+        });
+    }
+    save(state, &updated, actor)?;
+    *announcements = updated;
+
+    let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+    info!(state.lgr_important.borrow(), "Announcements removed"; "id" => InDebug(&id), "count" => removed, "user" => user, "request_id" => request_id);
+    Ok(format!("{} announcement(s) removed", removed))
+}
+
+pub fn list(state: &State) -> String {
+    let now = Utc::now();
+    let announcements = state.announcements.read().unwrap();
+    if announcements.is_empty() {
+        return "No announcements".to_string();
+    }
+    announcements
+        .iter()
+        .map(|x| x.describe(now))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The announcements shown on `page` right now, most severe first. Pages other than video pages
+/// and /list pass `Target::All`.
+pub fn markup(state: &State, page: Target) -> Markup {
+    let now = Utc::now();
+    let announcements = state.announcements.read().unwrap();
+    let mut active: Vec<&Announcement> = announcements
+        .iter()
+        .filter(|x| x.shows_on(page) && x.is_active(now))
+        .collect();
+    active.sort_by_key(|x| std::cmp::Reverse(x.severity as u8));
+    html! {
+        @if !active.is_empty() {
+            div class="announcements" {
+                @for announcement in active {
+                    div class={ "announcement " (announcement.severity.as_str()) } {
+                        (PreEscaped(&announcement.html))
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_and_schedules() {
+        let html = render("**Maintenance** <script>alert(1)</script> [more](https://x.org)");
+        assert!(html.contains("<strong>Maintenance</strong>"));
+        assert!(!html.contains("script"));
+        assert!(html.contains("href=\"https://x.org\""));
+
+        let announcement = parse_options(
+            "severity=warning from=2026-10-19T10:00 until=2026-10-20T10:00  Down *soon*",
+        )
+        .unwrap();
+        assert_eq!(announcement.severity, Severity::Warning);
+        assert_eq!(announcement.markdown, "Down *soon*");
+        assert!(!announcement.is_active(parse_time("2026-10-19T09:59").unwrap()));
+        assert!(announcement.is_active(parse_time("2026-10-19T12:00:00Z").unwrap()));
+        assert!(announcement.is_expired(parse_time("2026-10-20T10:00").unwrap()));
+
+        assert_eq!(announcement.target, Target::All);
+        assert!(announcement.shows_on(Target::List));
+
+        let announcement = parse_options("on=list Archive moved").unwrap();
+        assert!(announcement.shows_on(Target::List));
+        assert!(!announcement.shows_on(Target::Video));
+        assert!(!announcement.shows_on(Target::All));
+        assert!(parse_options("on=shell text").is_err());
+
+        assert_eq!(parse_options("a=b c").unwrap().markdown, "a=b c");
+        assert!(parse_options("severity=loud text").is_err());
+        assert!(parse_options("until=2026-10-19T10:00 from=2026-10-20T10:00 x").is_err());
+        assert!(parse_options("severity=info").is_err());
+    }
+}
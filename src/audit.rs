@@ -0,0 +1,198 @@
+use {
+    crate::{auth, logs::Backwards, privacy, requestid::RequestId, State},
+    actix_web::HttpRequest,
+    chrono::{DateTime, Utc},
+    fast_logger::error,
+    serde_derive::{Deserialize, Serialize},
+    std::{
+        fs::{File, OpenOptions},
+        io::{self, Write},
+    },
+};
+
+/// One JSON entry per line, only ever appended to. Kept outside of `files/` and not rotated, so
+/// it is neither served nor deleted with the request logs.
+pub static AUDIT_FILE: &str = "audit.jsonl";
+
+/// Longest user name written, failed logins record whatever name was typed
+static USER_LENGTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    /// The action was allowed but failed
+    Error,
+    /// Wrong password or token, or not allowed for the role or scope
+    Denied,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Error => "error",
+            Outcome::Denied => "denied",
+        }
+    }
+
+    pub fn parse(outcome: &str) -> Option<Self> {
+        match outcome {
+            "ok" => Some(Outcome::Ok),
+            "error" => Some(Outcome::Error),
+            "denied" => Some(Outcome::Denied),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub time: DateTime<Utc>,
+    /// The account, `token:<name>` for API tokens, or the name given to a failed login
+    pub user: String,
+    /// Anonymized like the client addresses in the other logs
+    pub client: String,
+    /// A shell command, an API route such as `POST /api/admin/rescan`, `login` or `logout`
+    pub action: String,
+    pub arguments: String,
+    pub outcome: Outcome,
+    /// The output or error of the action
+    pub message: String,
+    pub request_id: String,
+}
+
+impl Entry {
+    pub fn new(request: &HttpRequest, user: &str, action: &str, arguments: &str) -> Self {
+        Self {
+            time: Utc::now(),
+            user: user.chars().take(USER_LENGTH).collect(),
+            client: privacy::anonymize_ip(&auth::client(request)),
+            action: action.to_string(),
+            arguments: arguments.to_string(),
+            outcome: Outcome::Ok,
+            message: String::new(),
+            request_id: RequestId::of(request).to_string(),
+        }
+    }
+
+    pub fn result(mut self, result: &Result<String, String>) -> Self {
+        (self.outcome, self.message) = match result {
+            Ok(message) => (Outcome::Ok, message.clone()),
+            Err(err) => (Outcome::Error, err.clone()),
+        };
+        self
+    }
+
+    pub fn denied(mut self, reason: &str) -> Self {
+        self.outcome = Outcome::Denied;
+        self.message = reason.to_string();
+        self
+    }
+}
+
+/// The action of an API or form request, e.g. `POST /api/admin/rescan`
+pub fn route(request: &HttpRequest) -> String {
+    format!("{} {}", request.method(), request.path())
+}
+
+/// Append an entry, written with a single write so concurrent entries do not interleave
+pub fn record(state: &State, entry: Entry) {
+    let written = serde_json::to_string(&entry)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .and_then(|line| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(AUDIT_FILE)?
+                .write_all(format!("{}\n", line).as_bytes())
+        });
+    if let Err(err) = written {
+        let (action, user, request_id) = (entry.action, entry.user, entry.request_id);
+        error!(state.lgr_important.borrow(), "Unable to write the audit log"; "error" => err, "action" => action, "user" => user, "request_id" => request_id);
+    }
+}
+
+#[derive(Default)]
+pub struct Filter {
+    pub user: String,
+    /// Matches the start of the action, so `POST /api/admin` finds every API change
+    pub action: String,
+    pub outcome: Option<Outcome>,
+    pub contains: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    fn matches(&self, entry: &Entry) -> bool {
+        (self.user.is_empty() || entry.user == self.user)
+            && entry.action.starts_with(&self.action)
+            && self.outcome.map_or(true, |x| x == entry.outcome)
+            && (self.contains.is_empty()
+                || entry.arguments.contains(&self.contains)
+                || entry.message.contains(&self.contains))
+            && self.from.map_or(true, |x| entry.time >= x)
+            && self.to.map_or(true, |x| entry.time <= x)
+    }
+}
+
+/// The most recent `limit` entries matching `filter`, newest first. The file is read from the
+/// end, so only as far back as the entries found.
+pub fn search(filter: &Filter, limit: usize) -> io::Result<Vec<Entry>> {
+    let file = match File::open(AUDIT_FILE) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let mut found = Vec::with_capacity(limit);
+    for line in Backwards::new(file)? {
+        if found.len() == limit {
+            break;
+        }
+        let Ok(entry) = serde_json::from_str::<Entry>(&line?) else {
+            continue;
+        };
+        if filter.matches(&entry) {
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_entries() {
+        let entry: Entry = serde_json::from_str(
+            r#"{"time":"2026-10-19T10:00:00Z","user":"alice","client":"127.0.0.0","action":"POST /api/admin/rescan","arguments":"","outcome":"error","message":"Busy","request_id":"x"}"#,
+        )
+        .unwrap();
+        assert!(Filter::default().matches(&entry));
+        let filter = Filter {
+            user: "alice".to_string(),
+            action: "POST /api/admin".to_string(),
+            outcome: Some(Outcome::Error),
+            contains: "Bus".to_string(),
+            ..Filter::default()
+        };
+        assert!(filter.matches(&entry));
+        assert!(!Filter {
+            user: "bob".to_string(),
+            ..Filter::default()
+        }
+        .matches(&entry));
+        assert!(!Filter {
+            outcome: Some(Outcome::Denied),
+            ..Filter::default()
+        }
+        .matches(&entry));
+        assert!(!Filter {
+            from: Some(entry.time + chrono::Duration::seconds(1)),
+            ..Filter::default()
+        }
+        .matches(&entry));
+    }
+}
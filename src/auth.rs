@@ -0,0 +1,244 @@
+use {
+    crate::config::{
+        AUTH_FAILURES_GLOBAL, AUTH_FAILURES_PER_CLIENT, AUTH_LOCKOUT, AUTH_WINDOW, TRUSTED_PROXIES,
+    },
+    actix_web::{http::header, HttpRequest},
+    argon2::{
+        password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+        Argon2,
+    },
+    rand::Rng,
+    sha2::{Digest, Sha512},
+    std::{
+        collections::{HashMap, VecDeque},
+        net::IpAddr,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+    subtle::ConstantTimeEq,
+};
+
+/// Hash a password into the PHC string stored in the `password` file
+pub fn hash(password: &str) -> Result<String, String> {
+    let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>())
+        .map_err(|err| err.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|x| x.to_string())
+        .map_err(|err| err.to_string())
+}
+
+/// Files from before Argon2 contain the hex of an unsalted SHA-512
+pub fn is_legacy(stored: &str) -> bool {
+    !stored.starts_with('$')
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Check a password against the contents of the `password` file, errors when the file holds
+/// neither a PHC string nor SHA-512 hex
+pub fn verify(stored: &str, password: &str) -> Result<bool, String> {
+    if is_legacy(stored) {
+        let expected = decode_hex(stored).ok_or("Password file is not in hex format")?;
+        let mut hasher = Sha512::new();
+        hasher.update(password.as_bytes());
+        Ok(hasher.finalize()[..].ct_eq(&expected).into())
+    } else {
+        let hash = PasswordHash::new(stored).map_err(|err| err.to_string())?;
+        // Compares in constant time
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    }
+}
+
+fn is_trusted(address: &IpAddr) -> bool {
+    TRUSTED_PROXIES
+        .iter()
+        .any(|x| x.parse::<IpAddr>().ok() == Some(*address))
+}
+
+/// The address failed attempts are counted against. X-Forwarded-For is only read when the
+/// connection comes from a trusted proxy, and then from the right, since everything left of
+/// the last untrusted address could have been sent by the client.
+pub fn client(request: &HttpRequest) -> String {
+    let Some(peer) = request.peer_addr().map(|x| x.ip()) else {
+        return "-".to_string();
+    };
+    if !is_trusted(&peer) {
+        return peer.to_string();
+    }
+    let forwarded: Vec<&str> = request
+        .headers()
+        .get_all(header::X_FORWARDED_FOR)
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .map(str::trim)
+        .collect();
+    for address in forwarded.into_iter().rev() {
+        match address.parse::<IpAddr>() {
+            Ok(ip) if is_trusted(&ip) => continue,
+            Ok(ip) => return ip.to_string(),
+            // Whatever the proxy put there, it still identifies the client
+            Err(_) => return address.to_string(),
+        }
+    }
+    peer.to_string()
+}
+
+/// Counts failed password attempts per client and in total. After `limit` failures within
+/// `AUTH_WINDOW` the client (or everyone, for the global limit) is locked out for `AUTH_LOCKOUT`
+/// counted from the last failure.
+pub struct Throttle {
+    clients: Mutex<HashMap<String, VecDeque<Instant>>>,
+    global: Mutex<VecDeque<Instant>>,
+    global_limit: Option<usize>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            clients: Mutex::default(),
+            global: Mutex::default(),
+            global_limit: Some(AUTH_FAILURES_GLOBAL),
+        }
+    }
+}
+
+fn locked_for(failures: &VecDeque<Instant>, limit: usize, now: Instant) -> Option<Duration> {
+    let last = *failures.back()?;
+    let since = now.duration_since(last);
+    (failures.len() >= limit && since < AUTH_LOCKOUT).then(|| AUTH_LOCKOUT - since)
+}
+
+fn push(failures: &mut VecDeque<Instant>, now: Instant) {
+    failures.push_back(now);
+    while failures
+        .front()
+        .map_or(false, |x| now.duration_since(*x) > AUTH_WINDOW)
+    {
+        failures.pop_front();
+    }
+}
+
+impl Throttle {
+    /// Without the global limit, so one client guessing can not lock everyone else out
+    pub fn per_client() -> Self {
+        Self {
+            global_limit: None,
+            ..Self::default()
+        }
+    }
+
+    /// The time left when the client may not try a password right now
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        if let Some(limit) = self.global_limit {
+            if let Some(wait) = locked_for(&self.global.lock().unwrap(), limit, now) {
+                return Err(wait);
+            }
+        }
+        match self.clients.lock().unwrap().get(client) {
+            Some(failures) => match locked_for(failures, AUTH_FAILURES_PER_CLIENT, now) {
+                Some(wait) => Err(wait),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Record a failed attempt, returns whether the client is now locked out
+    pub fn failed(&self, client: &str) -> bool {
+        self.failed_at(client, Instant::now())
+    }
+
+    fn failed_at(&self, client: &str, now: Instant) -> bool {
+        if self.global_limit.is_some() {
+            push(&mut self.global.lock().unwrap(), now);
+        }
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= 10_000 {
+            clients.retain(|_, failures| {
+                failures.back().map_or(false, |x| {
+                    now.duration_since(*x) < AUTH_WINDOW.max(AUTH_LOCKOUT)
+                })
+            });
+        }
+        let failures = clients.entry(client.to_string()).or_default();
+        push(failures, now);
+        failures.len() >= AUTH_FAILURES_PER_CLIENT
+    }
+
+    pub fn succeeded(&self, client: &str) {
+        self.clients.lock().unwrap().remove(client);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, actix_web::test::TestRequest};
+
+    #[test]
+    fn verifies_and_throttles() {
+        let legacy = "ee26b0dd4af7e749aa1a8ee3c10ae9923f618980772e473f8819a5d4940e0db27ac185f8a0e1d5f84f88bc887fd67b143732c304cc5fa9ad8e6f57f50028a8ff";
+        assert_eq!(verify(legacy, "test"), Ok(true));
+        assert_eq!(verify(legacy, "tset"), Ok(false));
+        assert!(verify("abc", "test").is_err());
+        let phc = hash("test").unwrap();
+        assert!(phc.starts_with("$argon2id$") && !is_legacy(&phc));
+        assert_eq!(verify(&phc, "test"), Ok(true));
+        assert_eq!(verify(&phc, "tset"), Ok(false));
+
+        let throttle = Throttle::default();
+        let now = Instant::now();
+        for _ in 1..AUTH_FAILURES_PER_CLIENT {
+            assert!(!throttle.failed_at("a", now));
+        }
+        assert!(throttle.check_at("a", now).is_ok());
+        assert!(throttle.failed_at("a", now));
+        assert!(throttle.check_at("a", now).is_err());
+        assert!(throttle.check_at("b", now).is_ok());
+        assert!(throttle.check_at("a", now + AUTH_LOCKOUT).is_ok());
+        throttle.succeeded("a");
+        assert!(throttle.check_at("a", now).is_ok());
+
+        for throttle in [Throttle::default(), Throttle::per_client()] {
+            for client in 0..AUTH_FAILURES_GLOBAL {
+                throttle.failed_at(&client.to_string(), now);
+            }
+            assert_eq!(
+                throttle.check_at("b", now).is_err(),
+                throttle.global_limit.is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_client_behind_trusted_proxies() {
+        let request = |peer: &str, forwarded: &str| {
+            let mut request = TestRequest::default().peer_addr(peer.parse().unwrap());
+            if !forwarded.is_empty() {
+                request = request.insert_header((header::X_FORWARDED_FOR, forwarded));
+            }
+            client(&request.to_http_request())
+        };
+        assert_eq!(request("203.0.113.7:1234", "198.51.100.1"), "203.0.113.7");
+        assert_eq!(request("127.0.0.1:1234", ""), "127.0.0.1");
+        assert_eq!(request("127.0.0.1:1234", "198.51.100.1"), "198.51.100.1");
+        assert_eq!(
+            request("[::1]:1234", "198.51.100.1, 203.0.113.7, 127.0.0.1"),
+            "203.0.113.7"
+        );
+    }
+}
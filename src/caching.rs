@@ -0,0 +1,143 @@
+use {
+    crate::{
+        compression::{self, Encoding, Variants},
+        config::{CACHE_CONTROL_FILES, CACHE_CONTROL_FILES_DEFAULT},
+    },
+    actix_web::{
+        http::header::{self, HeaderMap, HttpDate},
+        HttpRequest, HttpResponse,
+    },
+    std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+        path::{Component, Path},
+        str::FromStr,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// A strong entity tag of `contents`, quoted as it is sent
+pub fn etag(contents: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Whether the client's copy is still current. `If-None-Match` is used instead of
+/// `If-Modified-Since` when both are sent, as RFC 9110 asks.
+pub fn is_fresh(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(tags) = headers.get(header::IF_NONE_MATCH) {
+        return tags.to_str().map_or(false, |tags| {
+            tags.split(',')
+                .map(|x| x.trim())
+                .any(|x| x == "*" || x.trim_start_matches("W/") == etag)
+        });
+    }
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| HttpDate::from_str(x).ok())
+        .map(SystemTime::from);
+    match (modified, since) {
+        // HTTP dates have no fractions of a second
+        (Some(modified), Some(since)) => modified < since + Duration::from_secs(1),
+        _ => false,
+    }
+}
+
+/// A generated page in the encoding the client prefers, with its validators, or 304 Not Modified
+/// when the client has it already
+pub fn respond(
+    request: &HttpRequest,
+    variants: &Variants,
+    etag: &str,
+    modified: Option<SystemTime>,
+    cache_control: &str,
+) -> HttpResponse {
+    let encoding = compression::negotiate(request.headers());
+    let etag = encoding.etag(etag);
+    let fresh = is_fresh(request.headers(), &etag, modified);
+    let mut response = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .insert_header((header::VARY, "Accept-Encoding"));
+    if let Some(modified) = modified {
+        response.insert_header((header::LAST_MODIFIED, HttpDate::from(modified)));
+    }
+    if encoding != Encoding::Identity {
+        response.insert_header((header::CONTENT_ENCODING, encoding.as_str()));
+    }
+    if fresh {
+        response.finish()
+    } else {
+        response
+            .content_type("text/html; charset=utf-8")
+            .body(variants.get(encoding))
+    }
+}
+
+/// The `Cache-Control` of a file below `files/`, chosen by its directory
+pub fn for_file(path: &Path) -> &'static str {
+    let directory = match path.components().next() {
+        Some(Component::Normal(directory)) => directory.to_str().unwrap_or_default(),
+        _ => "",
+    };
+    CACHE_CONTROL_FILES
+        .iter()
+        .find(|(name, _)| *name == directory)
+        .map(|(_, policy)| *policy)
+        .unwrap_or(CACHE_CONTROL_FILES_DEFAULT)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, actix_web::http::header::HeaderValue};
+
+    #[test]
+    fn checks_validators() {
+        let tag = etag(b"page");
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+        let headers = |name, value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+
+        assert!(!is_fresh(&HeaderMap::new(), &tag, Some(modified)));
+        assert!(is_fresh(
+            &headers(header::IF_NONE_MATCH, &format!("\"x\", W/{}", tag)),
+            &tag,
+            None
+        ));
+        assert!(!is_fresh(
+            &headers(header::IF_NONE_MATCH, &etag(b"old")),
+            &tag,
+            Some(modified)
+        ));
+        let since = HttpDate::from(modified).to_string();
+        assert!(is_fresh(
+            &headers(header::IF_MODIFIED_SINCE, &since),
+            &tag,
+            Some(modified)
+        ));
+        assert!(!is_fresh(
+            &headers(header::IF_MODIFIED_SINCE, &since),
+            &tag,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(!is_fresh(
+            &headers(header::IF_MODIFIED_SINCE, &since),
+            &tag,
+            None
+        ));
+
+        assert_eq!(for_file(Path::new("video/A.webm")), "no-cache");
+        assert_eq!(for_file(Path::new("js/video.js")), "public, max-age=3600");
+        assert_eq!(for_file(Path::new("misc/robots.txt")), "no-cache");
+    }
+}
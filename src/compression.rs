@@ -0,0 +1,237 @@
+use {
+    crate::config::COMPRESSIBLE_EXTENSIONS,
+    actix_web::{
+        http::header::{self, HeaderMap},
+        web::Bytes,
+    },
+    flate2::{write::GzEncoder, Compression},
+    std::{
+        fs,
+        io::{self, Write},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Directories below `files/` whose compressible files get `.gz` and `.br` siblings from
+/// `gondola-rs compress-assets`
+pub static ASSET_DIRECTORIES: [&str; 2] = ["files/css", "files/js"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    /// The `Content-Encoding` of this encoding
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// The file a precompressed copy of `path` is stored in
+    pub fn sibling(self, path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        match self {
+            Encoding::Identity => {}
+            Encoding::Gzip => name.push(".gz"),
+            Encoding::Brotli => name.push(".br"),
+        }
+        PathBuf::from(name)
+    }
+
+    /// Makes the ETag of an encoded copy differ from the original's, `"tag"` becomes `"tag-br"`
+    pub fn etag(self, etag: &str) -> String {
+        match self {
+            Encoding::Identity => etag.to_string(),
+            _ => format!("{}-{}\"", etag.trim_end_matches('"'), self.as_str()),
+        }
+    }
+}
+
+/// The encoding to send, brotli before gzip, identity when the client accepts neither
+pub fn negotiate(headers: &HeaderMap) -> Encoding {
+    let accepted: Vec<&str> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .filter_map(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next()?;
+            let refused = parts
+                .any(|x| x.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+            (!refused).then_some(name)
+        })
+        .collect();
+    let accepts = |name| {
+        accepted
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(name) || *x == "*")
+    };
+    if accepts("br") {
+        Encoding::Brotli
+    } else if accepts("gzip") {
+        Encoding::Gzip
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Only text is compressed, images and videos are compressed already
+pub fn is_compressible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|x| x.to_str())
+        .map_or(false, |x| COMPRESSIBLE_EXTENSIONS.contains(&x))
+}
+
+/// How hard to compress
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Level {
+    /// Slow, for what is compressed once per page or file
+    Best,
+    /// For pages compressed again whenever the announcements change
+    Fast,
+}
+
+/// Compressed at the highest level, which is slow but only done once per page or file
+pub fn compress(contents: &[u8], encoding: Encoding) -> Vec<u8> {
+    compress_at(contents, encoding, Level::Best)
+}
+
+fn compress_at(contents: &[u8], encoding: Encoding, level: Level) -> Vec<u8> {
+    let expect = "Compressing into memory does not fail";
+    let (gzip, brotli) = match level {
+        Level::Best => (Compression::best(), 11),
+        Level::Fast => (Compression::default(), 5),
+    };
+    match encoding {
+        Encoding::Identity => contents.to_vec(),
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), gzip);
+            encoder.write_all(contents).expect(expect);
+            encoder.finish().expect(expect)
+        }
+        Encoding::Brotli => {
+            let mut out = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, brotli, 22);
+                encoder.write_all(contents).expect(expect);
+            }
+            out
+        }
+    }
+}
+
+/// A page compressed once in every encoding, sent without compressing it again
+#[derive(Clone, Default)]
+pub struct Variants {
+    pub identity: Bytes,
+    pub gzip: Bytes,
+    pub brotli: Bytes,
+}
+
+impl Variants {
+    pub fn new(contents: Bytes) -> Self {
+        Self::at(contents, Level::Best)
+    }
+
+    pub fn fast(contents: Bytes) -> Self {
+        Self::at(contents, Level::Fast)
+    }
+
+    fn at(contents: Bytes, level: Level) -> Self {
+        Self {
+            gzip: Bytes::from(compress_at(&contents, Encoding::Gzip, level)),
+            brotli: Bytes::from(compress_at(&contents, Encoding::Brotli, level)),
+            identity: contents,
+        }
+    }
+
+    pub fn get(&self, encoding: Encoding) -> Bytes {
+        match encoding {
+            Encoding::Identity => self.identity.clone(),
+            Encoding::Gzip => self.gzip.clone(),
+            Encoding::Brotli => self.brotli.clone(),
+        }
+    }
+}
+
+/// `gondola-rs compress-assets`, writes a `.gz` and `.br` copy next to every compressible file in
+/// `ASSET_DIRECTORIES`, returns how many files were compressed
+pub fn compress_assets() -> io::Result<usize> {
+    let mut count = 0;
+    for directory in ASSET_DIRECTORIES {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if !path.is_file() || !is_compressible(&path) {
+                continue;
+            }
+            let contents = fs::read(&path)?;
+            for encoding in [Encoding::Gzip, Encoding::Brotli] {
+                fs::write(encoding.sibling(&path), compress(&contents, encoding))?;
+            }
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// The precompressed sibling of `path` to send instead, if there is one that is not older than
+/// `path` itself
+pub fn precompressed(path: &Path, encoding: Encoding) -> Option<PathBuf> {
+    if encoding == Encoding::Identity || !is_compressible(path) {
+        return None;
+    }
+    let sibling = encoding.sibling(path);
+    let modified = |x: &Path| fs::metadata(x).and_then(|x| x.modified()).ok();
+    (modified(&sibling)? >= modified(path)?).then_some(sibling)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, actix_web::http::header::HeaderValue, flate2::read::GzDecoder, std::io::Read};
+
+    #[test]
+    fn negotiates_and_compresses() {
+        let accept = |value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+            negotiate(&headers)
+        };
+        assert_eq!(accept("gzip, deflate, br"), Encoding::Brotli);
+        assert_eq!(accept("gzip, br;q=0"), Encoding::Gzip);
+        assert_eq!(accept("deflate"), Encoding::Identity);
+        assert_eq!(accept("*"), Encoding::Brotli);
+        assert_eq!(negotiate(&HeaderMap::new()), Encoding::Identity);
+
+        assert_eq!(Encoding::Brotli.etag("\"abc\""), "\"abc-br\"");
+        assert_eq!(
+            Encoding::Gzip.sibling(Path::new("files/css/style.css")),
+            Path::new("files/css/style.css.gz")
+        );
+        assert!(is_compressible(Path::new("files/js/video.js")));
+        assert!(!is_compressible(Path::new("files/video/A.webm")));
+
+        let page = "<tr><td>Gondola</td></tr>".repeat(100);
+        for variants in [
+            Variants::new(Bytes::from(page.clone())),
+            Variants::fast(Bytes::from(page.clone())),
+        ] {
+            assert!(variants.gzip.len() < page.len() && variants.brotli.len() < page.len());
+            let mut unzipped = String::new();
+            GzDecoder::new(&variants.gzip[..])
+                .read_to_string(&mut unzipped)
+                .unwrap();
+            assert_eq!(unzipped, page);
+            let mut unbrotli = String::new();
+            brotli::Decompressor::new(&variants.brotli[..], 4096)
+                .read_to_string(&mut unbrotli)
+                .unwrap();
+            assert_eq!(unbrotli, page);
+        }
+    }
+}
@@ -1,3 +1,12 @@
+use {
+    crate::{
+        accesslog::AccessLogFormat,
+        privacy::IpAnonymization,
+        writer::{LogFile, LogOutput, Rotate, Suffix},
+    },
+    std::time::Duration,
+};
+
 pub static PLURALITY: &str = "Gondolas"; // Part of <title> of the list page
 pub static LIST_TITLE: &str = "GondolaArchive"; // "name" of the /list page
 pub static DEFAULT_VIDEO: &str = "/FrontPage.webm"; // FrontPage, e.g. when accessing `gondola.com`
@@ -17,3 +26,87 @@ pub static BOARD: &str = "/gs/"; // /list board title
 pub static LOGLEVEL: u8 = 128; // Log level of the standard logger
 pub static LOGLEVEL_IMPORTANT: u8 = 255; // Log level of the important logger (trace = 255, debug = 192,
                                          // info = 128, warn = 64, error = 0)
+pub static LOG_VIEWER_LINES: usize = 1000; // Most recent matching lines shown on /logs
+pub static AUDIT_VIEWER_ENTRIES: usize = 500; // Most recent matching entries shown on /audit
+pub static DASHBOARD_VIDEOS: usize = 10; // Videos in the most viewed, recently added and without a source
+                                         // lists on /admin
+pub static DASHBOARD_LOG_LINES: usize = 20; // Most recent important log lines shown on /admin
+pub static SHELL_HISTORY_LENGTH: usize = 50; // Commands remembered on /shell until the server restarts
+pub static RESCAN_INTERVAL: Duration = Duration::from_secs(60 * 30); // Time between scans of files/video
+pub static AUTH_FAILURES_PER_CLIENT: usize = 5; // Wrong passwords from one address within AUTH_WINDOW
+                                                // before it is locked out
+pub static AUTH_FAILURES_GLOBAL: usize = 50; // Wrong passwords from all addresses within AUTH_WINDOW
+                                             // before everyone is locked out
+pub static AUTH_WINDOW: Duration = Duration::from_secs(60 * 15); // Period failed attempts are counted in
+pub static AUTH_LOCKOUT: Duration = Duration::from_secs(60 * 15); // Lockout after the last failed attempt
+pub static TRUSTED_PROXIES: [&str; 2] = ["127.0.0.1", "::1"]; // Reverse proxies whose X-Forwarded-For is
+                                                              // believed when throttling logins
+pub static SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60 * 12); // Time until /login has to be
+                                                                           // visited again
+pub static SESSION_COOKIE_SECURE: bool = true; // Only send the session cookie over HTTPS, browsers make an
+                                               // exception for localhost
+pub static UPLOAD_MAX_BYTES: usize = 200_000_000; // Largest video accepted on /upload
+pub static UPLOAD_EXTENSIONS: [&str; 2] = ["webm", "mp4"]; // Video types accepted on /upload
+pub static HISTORY_LENGTH: usize = 50; // Number of watched videos remembered per visitor (/history)
+pub static HISTORY_VISITORS: usize = 10_000; // Most visitors whose history is kept in memory, the visitors
+                                             // not seen for the longest are forgotten first
+pub static FRONT_PAGE_TODAY: bool = false; // Redirect the front page to the gondola of the day (/today)
+                                           // instead of DEFAULT_VIDEO
+pub static TODAY_NO_REPEAT_DAYS: usize = 30; // The gondola of the day does not repeat within this many
+                                             // days, unless there are fewer public videos than days
+pub static METRICS_PORT: Option<u16> = None; // Serve /metrics on its own TCP port, None serves it on PORT
+pub static ACCESS_LOG_FORMAT: AccessLogFormat = AccessLogFormat::Combined; // Format of files/logs/access,
+                                                                           // Combined or Json (one object per line)
+pub static IP_ANONYMIZATION: IpAnonymization = IpAnonymization::Truncate; // How client addresses are
+                                                                          // logged: None, Truncate (keep
+                                                                          // IPv4 /24 and IPv6 /48) or Hash
+pub static LOG_QUERY_STRINGS: bool = false; // Keep query strings of request paths in the logs
+pub static PERSONAL_DATA_RETENTION: Duration = Duration::from_secs(60 * 60 * 24 * 14); // Delete older logs
+pub static CACHE_CONTROL_LIST: &str = "public, no-cache"; // /list, revalidated with its ETag on every visit
+pub static CACHE_CONTROL_VIDEO_PAGE: &str = "private, no-cache"; // Video pages, which count views and set
+                                                                 // cookies, revalidated with a weak ETag
+pub static CACHE_CONTROL_FILES_DEFAULT: &str = "no-cache"; // Files in directories not listed below
+pub static COMPRESSIBLE_EXTENSIONS: [&str; 5] = ["css", "js", "txt", "svg", "json"]; // Files in files/ sent
+                                                                                     // gzip or brotli compressed
+                                                                                     // when a .gz or .br copy
+                                                                                     // exists, media never is
+
+// Cache-Control of /files/<directory>/..., the files are revalidated with their ETag and modification
+// time once this expires. style.css is reloaded after changes through its ?x= parameter, the scripts
+// are not. Videos are revalidated on every request, so hiding one stops it being served right away.
+pub static CACHE_CONTROL_FILES: [(&str, &str); 5] = [
+    ("css", "public, max-age=86400"),
+    ("js", "public, max-age=3600"),
+    ("favicon", "public, max-age=604800"),
+    ("images", "public, max-age=604800"),
+    ("video", "no-cache"),
+];
+
+// Where each log is written. LogOutput::Stdout sends the log to standard output (journald when
+// running under systemd). Files rotate by Rotate::Size(bytes), Rotate::Hourly or Rotate::Daily and
+// rotated files are named log.1, log.2, ... (Suffix::Count) or log.20230704T123000
+// (Suffix::Timestamp), keeping at most the given number of files.
+pub static LOG_SITE: LogOutput = LogOutput::File(LogFile {
+    // files/logs/log
+    rotate: Rotate::Size(100_000),
+    suffix: Suffix::Count(3),
+    compress: false,      // Gzip rotated files
+    max_age: None,        // Delete rotated files older than this
+    max_total_size: None, // Delete the oldest rotated files above this many bytes in total
+});
+pub static LOG_IMPORTANT: LogOutput = LogOutput::File(LogFile {
+    // files/logs/important
+    rotate: Rotate::Size(100_000),
+    suffix: Suffix::Count(3),
+    compress: false,
+    max_age: None,
+    max_total_size: None,
+});
+pub static LOG_ACCESS: LogOutput = LogOutput::File(LogFile {
+    // files/logs/access
+    rotate: Rotate::Daily,
+    suffix: Suffix::Timestamp(30),
+    compress: true,
+    max_age: Some(Duration::from_secs(60 * 60 * 24 * 30)),
+    max_total_size: Some(100_000_000),
+});
@@ -0,0 +1,224 @@
+use {
+    crate::{
+        announcements::{self, Target},
+        compute_time_ago,
+        config::*,
+        header, logout_form, logs, page_session,
+        users::Role,
+        State, VideoInfo, Visibility,
+    },
+    actix_web::{web, HttpRequest, HttpResponse, Responder},
+    chrono::{DateTime, Utc},
+    maud::{html, DOCTYPE},
+    std::{
+        cmp, fs, io,
+        sync::atomic::Ordering,
+        time::{Duration, SystemTime},
+    },
+};
+
+/// Size in bytes and number of the files in a directory, not counting subdirectories
+fn directory_size(path: &str) -> io::Result<(u64, usize)> {
+    let mut total = (0, 0);
+    for entry in fs::read_dir(path)? {
+        let metadata = entry?.metadata()?;
+        if metadata.is_file() {
+            total.0 += metadata.len();
+            total.1 += 1;
+        }
+    }
+    Ok(total)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < units.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    let time: DateTime<Utc> = time.into();
+    time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+fn ago(time: SystemTime) -> String {
+    match compute_time_ago(SystemTime::now(), time) {
+        (0, text) => text.to_string(),
+        (count, text) => format!("{} {}", count, text),
+    }
+}
+
+/// /admin, an overview of the videos, the state updater, disk usage and the important log
+pub async fn show(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    let session = match page_session(&state, &request, "/admin", Role::Admin) {
+        Ok(session) => session,
+        Err(response) => return response,
+    };
+
+    let videos: Vec<(String, VideoInfo)> = state
+        .video_info
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, info)| (name.clone(), info.clone()))
+        .collect();
+    let count = |visibility| {
+        videos
+            .iter()
+            .filter(|(_, info)| info.visibility == visibility)
+            .count()
+    };
+    let (public, unlisted, hidden) = (
+        count(Visibility::Public),
+        count(Visibility::Unlisted),
+        count(Visibility::Hidden),
+    );
+    let mut missing_source: Vec<&str> = videos
+        .iter()
+        .filter(|(_, info)| info.source.is_none())
+        .map(|(name, _)| name.as_str())
+        .collect();
+    missing_source.sort_unstable();
+    let missing_sources = missing_source.len();
+    missing_source.truncate(DASHBOARD_VIDEOS);
+    let mut most_viewed: Vec<&(String, VideoInfo)> = videos.iter().collect();
+    most_viewed.sort_by_key(|(_, info)| cmp::Reverse(info.views));
+    most_viewed.truncate(DASHBOARD_VIDEOS);
+    let mut recently_added: Vec<&(String, VideoInfo)> = videos.iter().collect();
+    recently_added.sort_by_key(|(_, info)| cmp::Reverse(info.added));
+    recently_added.truncate(DASHBOARD_VIDEOS);
+
+    let (disk, important) = match web::block(|| {
+        (
+            directory_size("files/video/"),
+            logs::tail("files/logs/important", DASHBOARD_LOG_LINES),
+        )
+    })
+    .await
+    {
+        Ok(read) => read,
+        Err(err) => (
+            Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+            Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+        ),
+    };
+    let health = &state.health;
+
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(0))
+                title { "Dashboard" }
+            }
+            body {
+                (announcements::markup(&state, Target::All))
+                a href="/shell" { "Shell" }
+                " "
+                a href="/logs" { "Logs" }
+                " "
+                a href="/audit" { "Audit" }
+                " "
+                a href="/upload" { "Upload" }
+                (logout_form(&session))
+                h4 { "Server" }
+                p {
+                    "Up for " (format_duration(state.started.elapsed())) br;
+                    @match state.metrics.last_rescan() {
+                        Some((time, duration)) => {
+                            "Last rescan at " (format_time(time)) ", took " (format!("{:.3}", duration.as_secs_f64())) "s"
+                            @if !health.last_rescan_ok.load(Ordering::Relaxed) {
+                                strong { " and had errors" }
+                            }
+                        }
+                        None => "No rescan yet",
+                    }
+                    br;
+                    "State updater " @if health.updater_running.load(Ordering::Relaxed) { "running" } @else { strong { "stopped" } }
+                    ", rescans every " (format_duration(RESCAN_INTERVAL))
+                }
+                h4 { "Videos" }
+                p {
+                    (videos.len()) " " (PLURALITY) ": " (public) " public, " (unlisted) " unlisted, " (hidden) " hidden" br;
+                    @match &disk {
+                        Ok((bytes, files)) => { "files/video holds " (files) " files, " (format_bytes(*bytes)) }
+                        Err(err) => { "Unable to read files/video: " (err.to_string()) }
+                    }
+                }
+                h4 { "Most viewed" }
+                ol {
+                    @for (name, info) in &most_viewed {
+                        li { a href=(format!("/{}", name)) { (name) } " (" (info.views) " views)" }
+                    }
+                }
+                h4 { "Recently added" }
+                ol {
+                    @for (name, info) in &recently_added {
+                        li { a href=(format!("/{}", name)) { (name) } " (" (ago(info.added)) ")" }
+                    }
+                }
+                h4 { (missing_sources) " without a source" }
+                ul {
+                    @for name in &missing_source {
+                        li { a href=(format!("/{}", name)) { (name) } }
+                    }
+                }
+                h4 { "Announcements" }
+                pre { (announcements::list(&state)) }
+                h4 { "Important log" }
+                @match important {
+                    Ok(lines) => {
+                        pre {
+                            @for line in lines {
+                                (line) "\n"
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        pre { "Unable to read the logs: " (err.to_string()) }
+                    }
+                }
+            }
+        }
+    };
+    HttpResponse::Ok().body(html.into_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_sizes_and_durations() {
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1_500_000), "1.5 MB");
+        assert_eq!(format_bytes(2_000_000_000_000_000), "2000.0 TB");
+        assert_eq!(format_duration(Duration::from_secs(59)), "0m 59s");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 120)),
+            "3h 2m"
+        );
+        assert_eq!(format_duration(Duration::from_secs(90000)), "1d 1h 0m");
+    }
+}
@@ -0,0 +1,91 @@
+use {
+    crate::config::{HISTORY_LENGTH, HISTORY_VISITORS},
+    std::collections::{HashMap, VecDeque},
+};
+
+/// The videos each visitor watched, newest last. Visitors are kept in `current` until it holds
+/// half of `HISTORY_VISITORS`, then it replaces `previous` and whoever was not seen since is
+/// forgotten, so a view is recorded in constant time.
+#[derive(Default)]
+pub struct History {
+    current: HashMap<String, VecDeque<String>>,
+    previous: HashMap<String, VecDeque<String>>,
+}
+
+impl History {
+    pub fn get(&self, visitor: &str) -> Option<&VecDeque<String>> {
+        self.current
+            .get(visitor)
+            .or_else(|| self.previous.get(visitor))
+    }
+
+    fn get_mut(&mut self, visitor: &str) -> Option<&mut VecDeque<String>> {
+        match self.current.get_mut(visitor) {
+            Some(watched) => Some(watched),
+            None => self.previous.get_mut(visitor),
+        }
+    }
+
+    pub fn record(&mut self, visitor: &str, video: &str) {
+        if !self.current.contains_key(visitor) {
+            let watched = self.previous.remove(visitor).unwrap_or_default();
+            if self.current.len() >= HISTORY_VISITORS / 2 {
+                self.previous = std::mem::take(&mut self.current);
+            }
+            self.current.insert(visitor.to_string(), watched);
+        }
+        let watched = self.current.get_mut(visitor).expect("Inserted above");
+        if watched.back().map_or(true, |x| x != video) {
+            watched.push_back(video.to_string());
+        }
+        while watched.len() > HISTORY_LENGTH {
+            watched.pop_front();
+        }
+    }
+
+    /// Find the video watched before `current` and forget everything watched after it
+    pub fn rewind(&mut self, visitor: &str, current: &str) -> Option<String> {
+        let watched = self.get_mut(visitor)?;
+        let index = watched.iter().rposition(|x| x == current)?;
+        let previous = watched.get(index.checked_sub(1)?)?.clone();
+        watched.truncate(index);
+        Some(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_and_forgets() {
+        let mut history = History::default();
+        for video in ["A.webm", "B.webm", "B.webm", "C.webm"] {
+            history.record("visitor", video);
+        }
+        assert_eq!(
+            history.get("visitor").unwrap(),
+            &["A.webm", "B.webm", "C.webm"]
+        );
+        assert_eq!(
+            history.rewind("visitor", "C.webm").as_deref(),
+            Some("B.webm")
+        );
+        assert_eq!(history.get("visitor").unwrap(), &["A.webm", "B.webm"]);
+        assert_eq!(history.rewind("visitor", "A.webm"), None);
+        assert_eq!(history.rewind("stranger", "A.webm"), None);
+
+        for index in 0..HISTORY_LENGTH * 2 {
+            history.record("visitor", &index.to_string());
+        }
+        assert_eq!(history.get("visitor").unwrap().len(), HISTORY_LENGTH);
+
+        for index in 0..HISTORY_VISITORS {
+            history.record(&index.to_string(), "A.webm");
+            history.record("visitor", "A.webm");
+        }
+        assert!(history.get("visitor").is_some());
+        assert!(history.get("0").is_none());
+        assert!(history.current.len() + history.previous.len() <= HISTORY_VISITORS);
+    }
+}
@@ -0,0 +1,284 @@
+use {
+    crate::writer::rotated_files,
+    chrono::{DateTime, FixedOffset, NaiveDateTime},
+    flate2::read::GzDecoder,
+    std::{
+        collections::VecDeque,
+        fs::File,
+        io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+        path::Path,
+    },
+};
+
+/// Level names understood by `loglevel` and the log viewer, most verbose first
+pub static LEVELS: [(&str, u8); 5] = [
+    ("trace", 255),
+    ("debug", 192),
+    ("info", 128),
+    ("warn", 64),
+    ("error", 0),
+];
+
+/// Parse a level name or number
+pub fn parse_level(level: &str) -> Option<u8> {
+    LEVELS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(level))
+        .map(|(_, value)| *value)
+        .or_else(|| level.parse().ok())
+}
+
+/// A line written by a `fast_logger::Logger`, `{time}: {level} {context}: {message}`
+#[derive(Debug, PartialEq)]
+struct Line<'a> {
+    time: DateTime<FixedOffset>,
+    level: u8,
+    context: &'a str,
+    message: &'a str,
+}
+
+fn parse_line(line: &str) -> Option<Line<'_>> {
+    let (time, rest) = line.split_once(": ")?;
+    let (level, rest) = rest.split_once(' ')?;
+    let (context, message) = rest.split_once(": ")?;
+    Some(Line {
+        time: DateTime::parse_from_str(time, "%b %d %Y %H:%M:%S%.f%z").ok()?,
+        level: level.parse().ok()?,
+        // Multi-line messages are written as `context [i/n]: line`
+        context: context.split(" [").next().unwrap_or_default(),
+        message,
+    })
+}
+
+/// Remove the terminal color codes the loggers write
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(x) = chars.next() {
+        if x == '\x1b' {
+            for y in chars.by_ref() {
+                if y.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(x);
+        }
+    }
+    out
+}
+
+#[derive(Default)]
+pub struct Filter {
+    /// Only lines at this level or more important
+    pub level: Option<u8>,
+    pub context: String,
+    pub contains: String,
+    /// Time range in UTC
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+impl Filter {
+    fn matches(&self, line: &str) -> bool {
+        if !self.contains.is_empty() && !line.contains(&self.contains) {
+            return false;
+        }
+        let Some(parsed) = parse_line(line) else {
+            return self.level.is_none()
+                && self.context.is_empty()
+                && self.from.is_none()
+                && self.to.is_none();
+        };
+        let time = parsed.time.naive_utc();
+        self.level.map_or(true, |x| parsed.level <= x)
+            && parsed.context.contains(&self.context)
+            && self.from.map_or(true, |x| time >= x)
+            && self.to.map_or(true, |x| time <= x)
+    }
+}
+
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    Ok(if path.extension().map_or(false, |x| x == "gz") {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
+
+/// The lines of a file from the last to the first, read in blocks from the end so a search for
+/// recent lines does not read the whole file
+pub struct Backwards {
+    file: File,
+    /// Where the part of the file that has not been read yet ends
+    position: u64,
+    /// Read but not returned yet, starts at `position`
+    buffer: Vec<u8>,
+}
+
+impl Backwards {
+    const BLOCK: u64 = 64 * 1024;
+
+    pub fn new(mut file: File) -> io::Result<Self> {
+        let mut position = file.metadata()?.len();
+        // The newline ending the last line does not start another one
+        if position > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::Start(position - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] == b'\n' {
+                position -= 1;
+            }
+        }
+        Ok(Self {
+            file,
+            position,
+            buffer: vec![],
+        })
+    }
+
+    fn read_block(&mut self) -> io::Result<()> {
+        let size = self.position.min(Self::BLOCK);
+        self.position -= size;
+        let mut block = vec![0; size as usize];
+        self.file.seek(SeekFrom::Start(self.position))?;
+        self.file.read_exact(&mut block)?;
+        block.extend_from_slice(&self.buffer);
+        self.buffer = block;
+        Ok(())
+    }
+}
+
+impl Iterator for Backwards {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = loop {
+            // The last line in the buffer is complete once the newline before it is read
+            if let Some(index) = self.buffer.iter().rposition(|x| *x == b'\n') {
+                let line = self.buffer.split_off(index + 1);
+                self.buffer.truncate(index);
+                break line;
+            }
+            if self.position == 0 {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                break std::mem::take(&mut self.buffer);
+            }
+            if let Err(err) = self.read_block() {
+                self.position = 0;
+                self.buffer.clear();
+                return Some(Err(err));
+            }
+        };
+        Some(Ok(String::from_utf8_lossy(&line)
+            .trim_end_matches('\r')
+            .to_string()))
+    }
+}
+
+/// The last `limit` lines of the log at `path`, without its rotated files, oldest first
+pub fn tail(path: &str, limit: usize) -> io::Result<Vec<String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let mut lines = Backwards::new(file)?
+        .take(limit)
+        .map(|line| line.map(|x| strip_ansi(&x)))
+        .collect::<io::Result<Vec<_>>>()?;
+    lines.reverse();
+    Ok(lines)
+}
+
+/// The last `limit` lines of the log at `path` and its rotated files matching `filter`
+pub fn search(path: &str, filter: &Filter, limit: usize) -> io::Result<Vec<String>> {
+    let mut found = VecDeque::with_capacity(limit);
+    let mut files = rotated_files(path);
+    files.push(path.into());
+    for file in files {
+        let mut reader = match open(&file) {
+            Ok(reader) => reader,
+            // Rotated away since listing the directory
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        let mut bytes = vec![];
+        loop {
+            bytes.clear();
+            if reader.read_until(b'\n', &mut bytes)? == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&bytes);
+            let line = strip_ansi(line.trim_end_matches(['\n', '\r']));
+            if filter.matches(&line) {
+                if found.len() == limit {
+                    found.pop_front();
+                }
+                found.push_back(line);
+            }
+        }
+    }
+    Ok(found.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_lines() {
+        let line = "\x1b[34mOct 19 2026 05:04:36.981870287+0000\x1b[0m: \x1b[32m064\x1b[0m request [2/7]: Request for non-existent file";
+        let line = strip_ansi(line);
+        let parsed = parse_line(&line).unwrap();
+        assert_eq!(parsed.level, 64);
+        assert_eq!(parsed.context, "request");
+        assert_eq!(parsed.message, "Request for non-existent file");
+
+        let at = |x| NaiveDateTime::parse_from_str(x, "%Y-%m-%dT%H:%M").ok();
+        let mut filter = Filter {
+            level: parse_level("warn"),
+            context: "req".to_string(),
+            contains: "non-existent".to_string(),
+            from: at("2026-10-19T05:04"),
+            to: at("2026-10-19T05:05"),
+        };
+        assert!(filter.matches(&line));
+        filter.level = parse_level("error");
+        assert!(!filter.matches(&line));
+        filter.level = None;
+        filter.from = at("2026-10-19T05:05");
+        assert!(!filter.matches(&line));
+        assert_eq!(parse_level("192"), Some(192));
+        assert_eq!(parse_level("loud"), None);
+    }
+
+    #[test]
+    fn reads_backwards() {
+        let path =
+            std::env::temp_dir().join(format!("gondola-backwards-{:016x}", rand::random::<u64>()));
+        let lines: Vec<String> = (0..20_000).map(|x| format!("line {}", x)).collect();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+        let read: Vec<String> = Backwards::new(File::open(&path).unwrap())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert!(read.iter().eq(lines.iter().rev()));
+
+        std::fs::write(&path, "a\r\n\nb").unwrap();
+        let read: Vec<String> = Backwards::new(File::open(&path).unwrap())
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, ["b", "", "a"]);
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(
+            Backwards::new(File::open(&path).unwrap()).unwrap().count(),
+            0
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
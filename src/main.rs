@@ -1,12 +1,26 @@
 #!(feature(proc_macro_hygiene)]
 use {
-    self::config::*,
+    self::{
+        accesslog::{AccessLog, Entry},
+        announcements::Target,
+        compression::Variants,
+        config::*,
+        history::History,
+        metrics::Metrics,
+        requestid::{RequestId, REQUEST_ID_HEADER},
+        session::{Session, Sessions, SESSION_COOKIE},
+        users::{Actor, Role, User},
+    },
     actix_files::NamedFile,
     actix_service::Service,
     actix_web::{
-        cookie::Cookie,
+        body::{BodySize, BoxBody, MessageBody},
+        cookie::{self, Cookie, SameSite},
         error,
-        http::{header::ContentType, StatusCode},
+        http::{
+            header::{self, ContentType, HeaderValue},
+            StatusCode, Uri,
+        },
         web,
         web::Data,
         App, HttpRequest, HttpResponse, HttpServer, Responder,
@@ -16,20 +30,21 @@ use {
     fast_logger::{error, info, trace, warn, Generic, InDebug, Logger},
     indexmap::IndexMap,
     maud::{html, Markup, PreEscaped, DOCTYPE},
-    rand::Rng,
+    rand::{seq::SliceRandom, Rng},
     rand_pcg::Pcg64Mcg as Random,
-    serde_derive::Deserialize,
+    serde_derive::{Deserialize, Serialize},
     sha2::{Digest, Sha512},
     std::{
         cell::RefCell,
         cmp,
+        collections::VecDeque,
         fs::{read_dir, File},
         io::{self, Read, Write},
-        num::ParseIntError,
-        path::PathBuf,
+        panic::{self, AssertUnwindSafe},
+        path::{Path, PathBuf},
         sync::{
-            atomic::{AtomicU64, Ordering},
-            Arc, RwLock,
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            mpsc, Arc, Mutex, RwLock,
         },
         thread,
         time::{Duration, Instant, SystemTime},
@@ -38,7 +53,25 @@ use {
 
 // ---
 
+mod accesslog;
+mod adminapi;
+mod announcements;
+mod audit;
+mod auth;
+mod caching;
+mod compression;
 mod config;
+mod dashboard;
+mod history;
+mod logs;
+mod metrics;
+mod privacy;
+mod requestid;
+mod session;
+mod shell;
+mod tokens;
+mod upload;
+mod users;
 mod writer;
 
 // ---
@@ -58,6 +91,8 @@ macro_rules! benchmark {
 static COOKIE_NAME: &str = "autoplay";
 static COOKIE_AUTOPLAY_RANDOM_VALUE: &str = "random";
 static COOKIE_AUTOPLAY_NEXT_VALUE: &str = "next";
+static COOKIE_VISITOR_NAME: &str = "visitor";
+static TODAY_FILE: &str = "files/statistics/.today";
 
 fn header(style_count: u64) -> Markup {
     let december = Utc::now().month() == 12;
@@ -107,12 +142,142 @@ fn header_list() -> Markup {
 
 // ---
 
-async fn index() -> impl Responder {
-    HttpResponse::PermanentRedirect()
-        .insert_header(("Location", DEFAULT_VIDEO))
+async fn index(state: web::Data<State>) -> impl Responder {
+    if FRONT_PAGE_TODAY {
+        play_todays_video(state).await
+    } else {
+        HttpResponse::PermanentRedirect()
+            .insert_header(("Location", DEFAULT_VIDEO))
+            .finish()
+    }
+}
+
+/// The gondola of `day` among `names`, skipping the `recent` picks unless every name is recent
+///
+/// Each name is ranked by hashing it together with the day and the first one wins, so adding,
+/// removing or hiding other videos never changes the pick unless an added video ranks first.
+/// SHA-512 hashes the same on every Rust release, unlike `DefaultHasher`, so the picks saved in
+/// `TODAY_FILE` stay valid across upgrades.
+fn gondola_of_the_day<'a>(names: &[&'a str], day: i64, recent: &[&str]) -> Option<&'a str> {
+    let rank = |name: &&str| {
+        let mut hasher = Sha512::new();
+        hasher.update(day.to_le_bytes());
+        hasher.update(name.as_bytes());
+        hasher.finalize()
+    };
+    let names = names.iter().copied();
+    names
+        .clone()
+        .filter(|x| !recent.contains(x))
+        .min_by_key(rank)
+        .or_else(|| names.min_by_key(rank))
+}
+
+fn todays_gondola(state: &State) -> Option<String> {
+    let today = Utc::now().date_naive();
+    if let Some((date, name)) = state.today.read().unwrap().back() {
+        if *date == today {
+            return Some(name.clone());
+        }
+    }
+
+    let public: Vec<String> = state
+        .video_info
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, info)| info.visibility == Visibility::Public)
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut picks = state.today.write().unwrap();
+    if let Some((date, name)) = picks.back() {
+        if *date == today {
+            return Some(name.clone());
+        }
+    }
+    let first = today - chrono::Duration::days(TODAY_NO_REPEAT_DAYS as i64);
+    picks.retain(|(date, _)| *date > first && *date < today);
+    let recent: Vec<&str> = picks.iter().map(|(_, name)| name.as_str()).collect();
+    let names: Vec<&str> = public.iter().map(String::as_str).collect();
+    let name = gondola_of_the_day(&names, today.num_days_from_ce() as i64, &recent)?.to_string();
+    picks.push_back((today, name.clone()));
+    save_todays_gondolas(state, &picks);
+    Some(name)
+}
+
+/// Pick the gondola of the day again once today's pick is removed, hidden or unlisted
+fn recheck_todays_gondola(state: &State) {
+    let today = Utc::now().date_naive();
+    let mut picks = state.today.write().unwrap();
+    let Some((date, name)) = picks.back() else {
+        return;
+    };
+    let public = state
+        .video_info
+        .read()
+        .unwrap()
+        .get(name)
+        .map_or(false, |info| info.visibility == Visibility::Public);
+    if *date == today && !public {
+        picks.pop_back();
+        save_todays_gondolas(state, &picks);
+    }
+}
+
+/// Kept on disk so the recent picks are not repeated after a restart, one `date name` per line
+fn save_todays_gondolas(state: &State, picks: &VecDeque<(NaiveDate, String)>) {
+    let contents: String = picks
+        .iter()
+        .map(|(date, name)| format!("{} {}\n", date, name))
+        .collect();
+    if let Err(err) = write_atomically(Path::new(TODAY_FILE), &contents) {
+        error!(state.lgr_important.borrow(), "Unable to write the gondolas of the day"; "error" => err);
+    }
+}
+
+fn load_todays_gondolas() -> io::Result<VecDeque<(NaiveDate, String)>> {
+    let contents = match std::fs::read_to_string(TODAY_FILE) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let (date, name) = line.split_once(' ')?;
+            Some((date.parse().ok()?, name.to_string()))
+        })
+        .collect())
+}
+
+async fn play_todays_video(state: web::Data<State>) -> HttpResponse {
+    let location = todays_gondola(&state)
+        .map(|name| String::from("/") + &name)
+        .unwrap_or_else(|| DEFAULT_VIDEO.to_string());
+    HttpResponse::TemporaryRedirect()
+        .insert_header(("Location", location))
         .finish()
 }
 
+#[derive(Serialize)]
+struct Today {
+    date: String,
+    name: String,
+    url: String,
+}
+
+async fn api_today(state: web::Data<State>) -> impl Responder {
+    if let Some(name) = todays_gondola(&state) {
+        HttpResponse::Ok().json(Today {
+            date: Utc::now().date_naive().to_string(),
+            url: format!("{}/{}", SITE_NAME, name),
+            name,
+        })
+    } else {
+        HttpResponse::NotFound().body("No videos in the archive")
+    }
+}
+
 #[derive(Debug, Display)]
 enum MyError {
     #[display(fmt = "unauthorized")]
@@ -135,30 +300,116 @@ impl error::ResponseError for MyError {
     }
 }
 
-async fn get_file(state: web::Data<State>, req: HttpRequest) -> actix_web::Result<NamedFile> {
-    let mut path = PathBuf::from("files/");
-    let rest = req
-        .match_info()
-        .query("filename")
-        .parse::<PathBuf>()
-        .unwrap();
-    path.push(&rest);
+/// Shown to browsers instead of a bare error, with the ID to mention when reporting the problem
+fn error_page(
+    status: StatusCode,
+    message: &str,
+    request_id: &RequestId,
+    style_count: u64,
+) -> String {
+    html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(style_count))
+                title { (status) }
+            }
+            body {
+                h4 { (status) }
+                @if !message.is_empty() {
+                    p { (message) }
+                }
+                p { "Request ID: " code { (request_id) } }
+                br;
+                a href="/" { "Return" }
+            }
+        }
+    }
+    .into_string()
+}
+
+async fn get_file(
+    state: web::Data<State>,
+    req: HttpRequest,
+    request_id: RequestId,
+) -> actix_web::Result<HttpResponse> {
+    let Some(rest) = file_path(req.match_info().query("filename")) else {
+        return Err(MyError::Unauthorized.into());
+    };
+    if is_private(&rest) {
+        return Err(error::ErrorNotFound("No such file"));
+    }
+    let path = Path::new("files/").join(&rest);
 
-    for item in path.components() {
-        if matches!(item, std::path::Component::ParentDir) {
-            return Err(MyError::Unauthorized.into());
+    if let Ok(video) = rest.strip_prefix("video") {
+        if is_hidden(&state, &video.to_string_lossy()) {
+            return Err(error::ErrorNotFound("No such video"));
         }
     }
 
-    match NamedFile::open(path) {
-        Ok(file) => Ok(file),
+    let encoding = compression::negotiate(req.headers());
+    let file = match compression::precompressed(&path, encoding) {
+        Some(compressed) => NamedFile::open(compressed).map(|file| {
+            let extension = path.extension().unwrap_or_default().to_string_lossy();
+            file.set_content_type(actix_files::file_extension_to_mime(&extension))
+                .set_content_encoding(match encoding {
+                    compression::Encoding::Brotli => header::ContentEncoding::Brotli,
+                    _ => header::ContentEncoding::Gzip,
+                })
+                .disable_content_disposition()
+        }),
+        None => NamedFile::open(&path),
+    };
+    match file {
+        // NamedFile sends the ETag and Last-Modified and answers conditional requests
+        Ok(file) => {
+            let mut response = file.into_response(&req);
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(caching::for_file(&rest)),
+            );
+            if compression::is_compressible(&path) {
+                headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+            }
+            Ok(response)
+        }
         Err(err) => {
-            warn!(state.lgr_important.borrow(), "Request for non-existent file"; "filename" => InDebug(&rest));
+            warn!(state.lgr_important.borrow(), "Request for non-existent file"; "filename" => InDebug(&rest), "request_id" => request_id);
             Err(err.into())
         }
     }
 }
 
+/// The path below files/ of a requested file, made only of plain names so checks on its
+/// components see the path that is opened
+fn file_path(filename: &str) -> Option<PathBuf> {
+    Path::new(filename)
+        .components()
+        .map(|item| match item {
+            std::path::Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Directories below files/ that are never served. The logs hold account names and client
+/// addresses, statistics and visibility name hidden videos.
+static PRIVATE_DIRECTORIES: [&str; 3] = ["logs", "statistics", "visibility"];
+
+fn is_private(path: &Path) -> bool {
+    PRIVATE_DIRECTORIES.iter().any(|x| path.starts_with(x))
+}
+
+fn is_hidden(state: &State, name: &str) -> bool {
+    state
+        .video_info
+        .read()
+        .unwrap()
+        .get(name)
+        .map_or(false, |info| info.visibility == Visibility::Hidden)
+}
+
 fn increment_view_count(state: &web::Data<State>, info: &str) {
     let mut video_infos = state.video_info.write().unwrap();
     if let Some(info) = video_infos.get_mut(info) {
@@ -166,13 +417,23 @@ fn increment_view_count(state: &web::Data<State>, info: &str) {
     }
 }
 
-async fn play_random_video_raw(state: web::Data<State>) -> impl Responder {
+/// A random video that is neither unlisted nor hidden
+fn random_public_video(state: &State) -> Option<String> {
     let video_infos = state.video_info.read().unwrap();
-    let index = state.random.borrow_mut().gen_range(0..video_infos.len());
-    let entry = video_infos.get_index(index);
-    if let Some(entry) = entry {
+    let public: Vec<&String> = video_infos
+        .iter()
+        .filter(|(_, info)| info.visibility == Visibility::Public)
+        .map(|(name, _)| name)
+        .collect();
+    public
+        .choose(&mut *state.random.borrow_mut())
+        .map(|name| name.to_string())
+}
+
+async fn play_random_video_raw(state: web::Data<State>, request_id: RequestId) -> impl Responder {
+    if let Some(name) = random_public_video(&state) {
         HttpResponse::TemporaryRedirect()
-            .insert_header(("Location", String::from("/files/video/") + entry.0))
+            .insert_header(("Location", String::from("/files/video/") + &name))
             .cookie(
                 Cookie::build(COOKIE_NAME, COOKIE_AUTOPLAY_RANDOM_VALUE)
                     .path("/")
@@ -180,7 +441,7 @@ async fn play_random_video_raw(state: web::Data<State>) -> impl Responder {
             )
             .finish()
     } else {
-        error!(state.lgr_important.borrow(), "Index does not exist"; "index" => index);
+        error!(state.lgr_important.borrow(), "No public video to play"; "request_id" => request_id);
         HttpResponse::TemporaryRedirect()
             .insert_header(("Location", "/"))
             .cookie(
@@ -192,13 +453,10 @@ async fn play_random_video_raw(state: web::Data<State>) -> impl Responder {
     }
 }
 
-async fn play_random_video(state: web::Data<State>) -> impl Responder {
-    let video_infos = state.video_info.read().unwrap();
-    let index = state.random.borrow_mut().gen_range(0..video_infos.len());
-    let entry = video_infos.get_index(index);
-    if let Some(entry) = entry {
+async fn play_random_video(state: web::Data<State>, request_id: RequestId) -> impl Responder {
+    if let Some(name) = random_public_video(&state) {
         HttpResponse::TemporaryRedirect()
-            .insert_header(("Location", String::from("/") + entry.0))
+            .insert_header(("Location", String::from("/") + &name))
             .cookie(
                 Cookie::build(COOKIE_NAME, COOKIE_AUTOPLAY_RANDOM_VALUE)
                     .path("/")
@@ -206,7 +464,7 @@ async fn play_random_video(state: web::Data<State>) -> impl Responder {
             )
             .finish()
     } else {
-        error!(state.lgr_important.borrow(), "Index does not exist"; "index" => index);
+        error!(state.lgr_important.borrow(), "No public video to play"; "request_id" => request_id);
         HttpResponse::TemporaryRedirect()
             .insert_header(("Location", "/"))
             .cookie(
@@ -218,23 +476,29 @@ async fn play_random_video(state: web::Data<State>) -> impl Responder {
     }
 }
 
-fn find_next_video(state: &web::Data<State>, path: &web::Path<String>) -> String {
+/// The first public video `offset(step)` positions away from `path`, wrapping around
+fn find_public_video(
+    state: &web::Data<State>,
+    path: &str,
+    offset: impl Fn(usize, usize, usize) -> usize,
+) -> String {
     let video_infos = state.video_info.read().unwrap();
-    let entry = video_infos.get_full(&**path);
-    if let Some(entry) = entry {
-        let index = entry.0;
-        if let Some(entry) = video_infos.get_index(index + 1) {
-            entry.0.into()
-        } else if let Some(entry) = video_infos.get_index(0) {
-            entry.0.into()
-        } else {
-            "".into()
-        }
+    let len = video_infos.len();
+    if let Some((index, _, _)) = video_infos.get_full(path) {
+        (1..=len)
+            .filter_map(|step| video_infos.get_index(offset(index, step, len)))
+            .find(|(_, info)| info.visibility == Visibility::Public)
+            .map(|(name, _)| name.clone())
+            .unwrap_or_default()
     } else {
         "".into()
     }
 }
 
+fn find_next_video(state: &web::Data<State>, path: &str) -> String {
+    find_public_video(state, path, |index, step, len| (index + step) % len)
+}
+
 async fn play_next_video(path: web::Path<String>) -> impl Responder {
     HttpResponse::TemporaryRedirect()
         .insert_header(("Location", String::from("/") + &path))
@@ -246,7 +510,84 @@ async fn play_next_video(path: web::Path<String>) -> impl Responder {
         .finish()
 }
 
-fn find_playmode(request: HttpRequest) -> PlayMode {
+fn find_previous_video(state: &web::Data<State>, path: &str) -> String {
+    find_public_video(state, path, |index, step, len| (index + len - step) % len)
+}
+
+async fn play_previous_video(path: web::Path<String>) -> impl Responder {
+    HttpResponse::TemporaryRedirect()
+        .insert_header(("Location", String::from("/") + &path))
+        .cookie(
+            Cookie::build(COOKIE_NAME, COOKIE_AUTOPLAY_NEXT_VALUE)
+                .path("/")
+                .finish(),
+        )
+        .finish()
+}
+
+/// The visitor ID from the cookie, IDs not made by `new_visitor` are ignored
+fn find_visitor(request: &HttpRequest) -> Option<String> {
+    request
+        .cookie(COOKIE_VISITOR_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|x| x.len() == 32 && x.bytes().all(|x| x.is_ascii_hexdigit()))
+}
+
+/// A random visitor ID, unguessable so nobody can read another visitor's /history
+fn new_visitor() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+async fn play_previous_random_video(
+    state: web::Data<State>,
+    path: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let previous = find_visitor(&request)
+        .and_then(|visitor| state.history.write().unwrap().rewind(&visitor, &path))
+        .unwrap_or_else(|| find_previous_video(&state, &path));
+    HttpResponse::TemporaryRedirect()
+        .insert_header(("Location", String::from("/") + &previous))
+        .cookie(
+            Cookie::build(COOKIE_NAME, COOKIE_AUTOPLAY_RANDOM_VALUE)
+                .path("/")
+                .finish(),
+        )
+        .finish()
+}
+
+async fn show_history(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    let watched = find_visitor(&request)
+        .and_then(|visitor| state.history.read().unwrap().get(&visitor).cloned())
+        .unwrap_or_default();
+
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(state.style_count.load(Ordering::Relaxed)))
+                title { "Recently Watched" }
+            }
+            body {
+                (announcements::markup(&state, Target::All))
+                h4 { "Recently watched (most recent first)" }
+                @if watched.is_empty() {
+                    p { "Nothing watched yet" }
+                }
+                ol {
+                    @for video in watched.iter().rev() {
+                        li { a href=(&(String::from("/") + video)) { (video) } }
+                    }
+                }
+                br;
+                a href="/" { "Return" }
+            }
+        }
+    };
+    HttpResponse::Ok().body(html.into_string())
+}
+
+fn find_playmode(request: &HttpRequest) -> PlayMode {
     if let Some(cookie) = request.cookie(COOKIE_NAME) {
         if cookie.value() == COOKIE_AUTOPLAY_RANDOM_VALUE {
             return PlayMode::Random;
@@ -314,131 +655,193 @@ fn compute_time_ago(now: SystemTime, then: SystemTime) -> (u32, &'static str) {
     }
 }
 
-fn generate_list_page(state: &mut State) {
-    let video_infos = state.video_info.read().unwrap();
+fn generate_list_page(state: &State) {
+    let today = todays_gondola(state);
+    let video_infos: IndexMap<String, VideoInfo> = state
+        .video_info
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, info)| info.visibility == Visibility::Public)
+        .map(|(name, info)| (name.clone(), info.clone()))
+        .collect();
     let video_infos_clone_date = video_infos.clone();
 
-    let html = html! {
-        (DOCTYPE)
-        html {
-            head {
-                (header_list())
-                title { "All " (PLURALITY) " - " (LIST_TITLE) }
-            }
-            body {
-                div class="boardBanner" {
-                    div id="bannerCnt" class="title desktop" data-src="/files/images/banner.png" {
-                        img alt=(NAME) src="/files/images/banner.png";
-                    }
-                    div class="boardTitle" { (format!("{} - {}", BOARD, NAME)) }
+    let html = benchmark! {
+        |duration| state.metrics.observe_list_render(duration),
+        html! {
+            (DOCTYPE)
+            html {
+                head {
+                    (header_list())
+                    title { "All " (PLURALITY) " - " (LIST_TITLE) }
                 }
-                div class="navLinks mobile" {
-                    span class="mobileib button" { a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } }
-                    span class="mobileib button" { a href="/random" title="Redirects to a random Gondola" { "Random" } }
-                    span class="mobileib button" { a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } }
-                    span class="mobileib button" { a href="#bottom" { "Bottom" } }
-                }
-                hr class="desktop";
-                div class="navLinks desktop" {
-                    "[" a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } "]"
-                    "[" a href="/random" title="Redirects to a random Gondola" { "Random" } "]"
-                    "[" a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } "]"
-                    "[" a href="#bottom" { "Bottom" } "]"
-                }
-                hr;
-                h4 class="center" {
-                    "Videos can be looped in most browsers: right-click + loop" br; "Videos normally autoplay." br; "If you click Next (ordered) autoplay will play  sequentially, if you click Next (random) autoplay will play in random order." br;
-                    strong { "Gondola suggestions: " } (EMAIL)
-                }
-                h4 class="center" {
-                    "There are " span class="rainbow-block" { (video_infos.len()) } " " (PLURALITY) " in this archive. "
-                    span class="rainbow-block" {
-                        ({
-                            let mut count = 0;
-                            for (_, video_info) in &*video_infos {
-                                if video_info.source.is_some() {
-                                    count += 1;
-                                }
-                            }
-                            format!["{:.2}%", (count * 100) as f32 / video_infos.len() as f32]
-                        })
+                body {
+                    (PreEscaped(announcements::SLOT))
+                    div class="boardBanner" {
+                        div id="bannerCnt" class="title desktop" data-src="/files/images/banner.png" {
+                            img alt=(NAME) src="/files/images/banner.png";
+                        }
+                        div class="boardTitle" { (format!("{} - {}", BOARD, NAME)) }
                     }
-                    " of " (PLURALITY) " have a source."
-                }
-                table id="arc-list" class="flashListing sortable" {
-                    thead {
-                        tr {
-                            td class="postblock" { "Gondola Name" }
-                            td class="postblock" { "Source" }
-                            td class="postblock" { "Views" }
-                            td class="postblock" { "Date Added" }
-                            td class="postblock" { "Ago" }
+                    div class="navLinks mobile" {
+                        span class="mobileib button" { a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } }
+                        span class="mobileib button" { a href="/random" title="Redirects to a random Gondola" { "Random" } }
+                        span class="mobileib button" { a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } }
+                        span class="mobileib button" { a href="#bottom" { "Bottom" } }
+                    }
+                    hr class="desktop";
+                    div class="navLinks desktop" {
+                        "[" a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } "]"
+                        "[" a href="/random" title="Redirects to a random Gondola" { "Random" } "]"
+                        "[" a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } "]"
+                        "[" a href="#bottom" { "Bottom" } "]"
+                    }
+                    hr;
+                    @if let Some(today) = &today {
+                        h4 class="center" {
+                            (SINGULAR) " of the day: " a href=(today) { (today) }
+                        }
+                    }
+                    h4 class="center" {
+                        "Videos can be looped in most browsers: right-click + loop" br; "Videos normally autoplay." br; "If you click Next (ordered) autoplay will play  sequentially, if you click Next (random) autoplay will play in random order." br;
+                        strong { "Gondola suggestions: " } (EMAIL)
+                    }
+                    h4 class="center" {
+                        "There are " span class="rainbow-block" { (video_infos.len()) } " " (PLURALITY) " in this archive. "
+                        span class="rainbow-block" {
+                            ({
+                                let mut count = 0;
+                                for (_, video_info) in &video_infos {
+                                    if video_info.source.is_some() {
+                                        count += 1;
+                                    }
+                                }
+                                format!["{:.2}%", (count * 100) as f32 / video_infos.len() as f32]
+                            })
                         }
+                        " of " (PLURALITY) " have a source."
                     }
-                    tbody {
-                        @for (video_name, video_info) in video_infos_clone_date.sorted_by(sorted_by_date) {
+                    table id="arc-list" class="flashListing sortable" {
+                        thead {
                             tr {
-                                td { a href=(video_name) { (video_name) } }
-                                td { (video_info.source.as_ref().unwrap_or(&String::new())) }
-                                td { (video_info.views) }
-                                td { ({
-                                    let datetime: DateTime<Utc> = video_info.added.into();
-                                    datetime.format("%A, %B %d, %Y %T")
-                                }) }
-                                td { ({
-                                    let ago = compute_time_ago(SystemTime::now(), video_info.added);
-                                    if ago.0 == 0 {
-                                        ago.1.to_string()
-                                    } else {
-                                        format!["{} {}", ago.0, ago.1]
+                                td class="postblock" { "Gondola Name" }
+                                td class="postblock" { "Source" }
+                                td class="postblock" { "Views" }
+                                td class="postblock" { "Date Added" }
+                                td class="postblock" { "Ago" }
+                            }
+                        }
+                        tbody {
+                            @for (video_name, video_info) in video_infos_clone_date.sorted_by(sorted_by_date) {
+                                tr {
+                                    td { a href=(video_name) { (video_name) } }
+                                    td { (video_info.source.as_ref().unwrap_or(&String::new())) }
+                                    td { (video_info.views) }
+                                    td { ({
+                                        let datetime: DateTime<Utc> = video_info.added.into();
+                                        datetime.format("%A, %B %d, %Y %T")
+                                    }) }
+                                    td { ({
+                                        let ago = compute_time_ago(SystemTime::now(), video_info.added);
+                                        if ago.0 == 0 {
+                                            ago.1.to_string()
+                                        } else {
+                                            format!["{} {}", ago.0, ago.1]
+                                        }
+                                    })
                                     }
-                                })
                                 }
                             }
                         }
                     }
-                }
-                hr;
-                div class="navLinks navLinksBot desktop" {
-                    "[" a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } "]"
-                    "[" a href="/random" title="Redirects to a random Gondola" { "Random" } "]"
-                    "[" a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } "]"
-                    "[" a href="#top" { "Top" } "]"
-                }
-                hr class="desktop";
-                div class="navLinks mobile" {
-                    span class="mobileib button" { a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } }
-                    span class="mobileib button" { a href="/random" title="Redirects to a random Gondola" { "Random" } }
-                    span class="mobileib button" { a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } }
-                    span class="mobileib button" { a href="#top" { "Top" } }
-                }
-                hr class="mobile";
-
-                div class="cssDropdown" {
-                    span class="stylechanger" {
-                        "Style: "
-                        select id="swapCSS" onchange="swapCSS()" {
-                            option value="/files/css/yotsuba.css" { "Yotsuba" }
-                            option value="/files/css/yotsublue.css" { "Yotsuba Blue" }
+                    hr;
+                    div class="navLinks navLinksBot desktop" {
+                        "[" a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } "]"
+                        "[" a href="/random" title="Redirects to a random Gondola" { "Random" } "]"
+                        "[" a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } "]"
+                        "[" a href="#top" { "Top" } "]"
+                    }
+                    hr class="desktop";
+                    div class="navLinks mobile" {
+                        span class="mobileib button" { a href=(format!("https://disqus.com/home/forum/{}/", FORUM_NAME)) { "View All Comments" } }
+                        span class="mobileib button" { a href="/random" title="Redirects to a random Gondola" { "Random" } }
+                        span class="mobileib button" { a href="/random-raw" title="Redirects to a random Gondola video stream" { "Random Raw" } }
+                        span class="mobileib button" { a href="#top" { "Top" } }
+                    }
+                    hr class="mobile";
+
+                    div class="cssDropdown" {
+                        span class="stylechanger" {
+                            "Style: "
+                            select id="swapCSS" onchange="swapCSS()" {
+                                option value="/files/css/yotsuba.css" { "Yotsuba" }
+                                option value="/files/css/yotsublue.css" { "Yotsuba Blue" }
+                            }
                         }
                     }
-                }
 
-                div id="bottom" {}
-                script type="text/javascript" {
-                    (PreEscaped("function swapCSS() { var x = document.getElementById(\"swapCSS\").value; document.getElementById(\"pageStyle\").setAttribute(\"href\", x); }"))
+                    div id="bottom" {}
+                    script type="text/javascript" {
+                        (PreEscaped("function swapCSS() { var x = document.getElementById(\"swapCSS\").value; document.getElementById(\"pageStyle\").setAttribute(\"href\", x); }"))
+                    }
+                    script type="text/javascript" src="/files/js/sorttable.js" {}
                 }
-                script type="text/javascript" src="/files/js/sorttable.js" {}
             }
         }
     };
 
-    *state.listpage.write().unwrap() = html.into_string();
+    let mut html = html.into_string();
+    let slot = html
+        .find(announcements::SLOT)
+        .expect("The list page has a slot for the announcements");
+    html.replace_range(slot..slot + announcements::SLOT.len(), "");
+    *state.listpage.write().unwrap() = ListPage {
+        etag: caching::etag(html.as_bytes()),
+        variants: Variants::new(web::Bytes::from(html)),
+        slot,
+        generated: SystemTime::now(),
+        announced: Arc::default(),
+    };
 }
 
-async fn list_all_videos(state: web::Data<State>) -> impl Responder {
-    let listpage = state.listpage.read().unwrap();
-    HttpResponse::Ok().body(listpage.clone())
+async fn list_all_videos(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    let page = state.listpage.read().unwrap().clone();
+    let announcements = announcements::markup(&state, Target::List).into_string();
+    if announcements.is_empty() {
+        return caching::respond(
+            &request,
+            &page.variants,
+            &page.etag,
+            Some(page.generated),
+            CACHE_CONTROL_LIST,
+        );
+    }
+    // Announcements come and go without a new list page, so only the ETag tells whether it changed
+    let etag = caching::etag(format!("{}{}", page.etag, announcements).as_bytes());
+    let cached = page.announced.lock().unwrap().clone();
+    let variants = match cached {
+        Some((tag, variants)) if tag == etag => variants,
+        // Compressed off the async workers and without holding the lock, requests meanwhile
+        // compress it too rather than wait
+        _ => {
+            let html = page.variants.identity.clone();
+            let compressed = web::block(move || {
+                let mut with_announcements = Vec::with_capacity(html.len() + announcements.len());
+                with_announcements.extend_from_slice(&html[..page.slot]);
+                with_announcements.extend_from_slice(announcements.as_bytes());
+                with_announcements.extend_from_slice(&html[page.slot..]);
+                Variants::fast(web::Bytes::from(with_announcements))
+            })
+            .await;
+            let Ok(variants) = compressed else {
+                return HttpResponse::InternalServerError().body("Unable to render the list");
+            };
+            *page.announced.lock().unwrap() = Some((etag.clone(), variants.clone()));
+            variants
+        }
+    };
+    caching::respond(&request, &variants, &etag, None, CACHE_CONTROL_LIST)
 }
 
 async fn render_video_page(
@@ -446,19 +849,85 @@ async fn render_video_page(
     info: web::Path<String>,
     request: HttpRequest,
 ) -> impl Responder {
-    let next_video = find_next_video(&state, &info);
+    let play_mode = find_playmode(&request);
+    render_video(&state, &info, play_mode, None, &request)
+}
+
+/// Where the view count goes on video pages. The ETag is taken before it is filled in, so a
+/// page stays fresh in the browser when only the view count changed.
+static VIEWS_SLOT: &str = "<!--views-->";
+
+struct PlaylistPosition {
+    id: String,
+    index: usize,
+    len: usize,
+    previous: String,
+    next: String,
+}
+
+fn render_video(
+    state: &web::Data<State>,
+    info: &str,
+    play_mode: PlayMode,
+    playlist: Option<PlaylistPosition>,
+    request: &HttpRequest,
+) -> HttpResponse {
+    if is_hidden(state, info) {
+        return HttpResponse::NotFound().body("No such video");
+    }
+
+    let next_video;
+    let next_url;
+    if let Some(playlist) = &playlist {
+        next_video = playlist.next.clone();
+        next_url = format!(
+            "/playlist/{}/{}",
+            playlist.id,
+            playlist.index % playlist.len + 1
+        );
+    } else {
+        next_video = find_next_video(state, info);
+        next_url = String::from("/next/") + &next_video;
+    }
 
-    let play_mode = find_playmode(request);
+    let path = String::from("/files/video/") + info;
 
-    let path = String::from("/files/video/") + &info;
+    increment_view_count(state, info);
+
+    let visitor = find_visitor(request);
+    let is_new_visitor = visitor.is_none();
+    let visitor = visitor.unwrap_or_else(new_visitor);
+    if state.video_info.read().unwrap().contains_key(info) {
+        state.history.write().unwrap().record(&visitor, info);
+    }
 
-    increment_view_count(&state, &info);
+    let previous_video;
+    let previous_url;
+    if let Some(playlist) = &playlist {
+        previous_video = Some(playlist.previous.clone());
+        previous_url = format!(
+            "/playlist/{}/{}",
+            playlist.id,
+            (playlist.index + playlist.len - 2) % playlist.len + 1
+        );
+    } else if play_mode == PlayMode::Random {
+        previous_video = state
+            .history
+            .read()
+            .unwrap()
+            .get(&visitor)
+            .and_then(|watched| watched.iter().rev().nth(1).cloned());
+        previous_url = String::from("/back/") + info;
+    } else {
+        let previous = find_previous_video(state, info);
+        previous_url = String::from("/prev/") + &previous;
+        previous_video = Some(previous);
+    }
 
     let video_infos = state.video_info.read().unwrap();
     let default_video_info = VideoInfo::default();
-    let video_info = video_infos.get(&*info).unwrap_or(&default_video_info);
+    let video_info = video_infos.get(info).unwrap_or(&default_video_info);
     let video_count = video_infos.len();
-    let announcement = state.announcement.read().unwrap();
 
     let html = html! {
         (DOCTYPE)
@@ -469,16 +938,12 @@ async fn render_video_page(
                 script type="text/javascript" {
                     (PreEscaped("var forum_url = \"")) (FORUM_NAME) (PreEscaped("\";"))
                     (PreEscaped("var random_url = \"/random\";"))
-                    (PreEscaped("var next_url = \"/next/")) (next_video) (PreEscaped("\";"))
+                    (PreEscaped("var next_url = \"")) (next_url) (PreEscaped("\";"))
                     "var play_random = " @if play_mode == PlayMode::Random { "true" } @else { "false" } ";"
                 }
             }
             body class="main" {
-                @if let Some(announcement) = &*announcement {
-                    div class="announcement" {
-                        (PreEscaped(announcement))
-                    }
-                }
+                (announcements::markup(state, Target::Video))
                 div class="video" {
                     video id="video" width="100%" height="100%" autoplay="true" onclick="toggle_pause();" onvolumechange="store_volume();" controls="" {
                         source src=(&path) type="video/webm";
@@ -486,6 +951,19 @@ async fn render_video_page(
                 }
                 script type="text/javascript" src="/files/js/video.js" {}
                 div class="bottom" {
+                    a class="button" href=(&previous_url) {
+                        div class="center" {
+                            span class="small" {
+                                (previous_video.as_deref().unwrap_or("Nothing watched before this"))
+                                br;
+                            }
+                            @match play_mode {
+                                PlayMode::Random => "Previous (random)",
+                                PlayMode::Sequential => "Previous (ordered)",
+                                PlayMode::Playlist => "Previous (playlist)",
+                            }
+                        }
+                    }
                     a class="button" href="/random" {
                         div class="center" {
                             span class="small" {
@@ -501,25 +979,31 @@ async fn render_video_page(
                             }
                         }
                     }
-                    a class="button" href=(&(String::from("/next/") + &next_video)) {
+                    a class="button" href=(&next_url) {
                         div class="center" {
                             span class="small" {
                                 (next_video)
                                 br;
                             }
-                            "Next (ordered)";
-                            @if play_mode == PlayMode::Sequential {
+                            @if let Some(playlist) = &playlist {
+                                "Next (playlist)";
                                 br;
-                                span class="autoplay" { "autoplaying sequential" }
+                                span class="autoplay" { "autoplaying playlist " (playlist.index) " / " (playlist.len) }
+                            } @else {
+                                "Next (ordered)";
+                                @if play_mode == PlayMode::Sequential {
+                                    br;
+                                    span class="autoplay" { "autoplaying sequential" }
+                                }
                             }
                         }
                     }
                     div class="button" onclick="show_comments();"{
                         div class="center" {
-                            (video_info.views) " views";
+                            (PreEscaped(VIEWS_SLOT)) " views";
                             br;
                             "Show "
-                            a id="disqus_comments" href=(&(String::from("") + SITE_NAME + "/" + &*info + "#disqus_thread")) {
+                            a id="disqus_comments" href=(&(String::from("") + SITE_NAME + "/" + info + "#disqus_thread")) {
                                 span class="loading" { "" }
                                 " Comments"
                             }
@@ -533,6 +1017,7 @@ async fn render_video_page(
                         }
                     }
                 }
+                a class="small" href="/history" { "History" }
                 div id="disqus_thread" hidden="";
                 script type="text/javascript" src="files/js/disqus.js" {}
                 script async="" id="dsq-count-scr" src=(&(String::from("//") + FORUM_NAME + ".disqus.com/count.js")) {}
@@ -540,201 +1025,1210 @@ async fn render_video_page(
             }
         }
     };
-    HttpResponse::Ok().body(html.into_string())
-}
-
-async fn unknown_route(state: web::Data<State>, request: HttpRequest) -> impl Responder {
-    let request_string = format!("{:#?}", request);
-    info!(state.lgr.borrow(), "Unknown route accessed"; "request" => request_string);
-    HttpResponse::TemporaryRedirect()
-        .insert_header(("Location", "/"))
-        .finish()
-}
-
-// ---
-
-#[derive(Clone, Deserialize)]
-struct ShellCommandForm {
-    act: String,
-    key: String,
-}
-
-enum RanState {
-    NoCommandToRun,
-    WrongPassword,
-    PasswordNotHex,
-    RanCommand(String),
+    let views = video_info.views;
+    drop(video_infos);
+
+    let page = html.into_string();
+    let etag = caching::etag(page.as_bytes());
+    // The view is counted either way, the page only differs from the cached one in the count
+    let fresh = caching::is_fresh(request.headers(), &etag, None);
+    let mut response = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::CACHE_CONTROL, CACHE_CONTROL_VIDEO_PAGE))
+        .insert_header((header::ETAG, format!("W/{}", etag)));
+    if is_new_visitor {
+        response.cookie(
+            Cookie::build(COOKIE_VISITOR_NAME, visitor)
+                .path("/")
+                .http_only(true)
+                .permanent()
+                .finish(),
+        );
+    }
+    if fresh {
+        return response.finish();
+    }
+    response.body(page.replacen(VIEWS_SLOT, &views.to_string(), 1))
 }
 
-pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
-    (0..s.len())
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
-        .collect()
-}
+async fn render_playlist_page(
+    state: web::Data<State>,
+    path: web::Path<(String, usize)>,
+    request: HttpRequest,
+) -> impl Responder {
+    let (id, index) = path.into_inner();
+    let videos = match playlist_videos(&state, &id) {
+        Some(videos) if !videos.is_empty() => videos,
+        _ => return HttpResponse::NotFound().body("No such playlist"),
+    };
 
-async fn do_shell(state: web::Data<State>, form: web::Form<ShellCommandForm>) -> impl Responder {
-    let ran_command;
-
-    if !form.act.is_empty() && !form.key.is_empty() {
-        let act_clone = form.act.clone();
-        info!(state.lgr.borrow(), "Running shell"; "act" => act_clone);
-        if let Ok(password) = slurp(&PathBuf::from("password")) {
-            let password = password.trim();
-            if let Ok(pw) = decode_hex(password) {
-                let mut hasher = Sha512::new();
-                hasher.update(form.key.as_bytes());
-                let result = hasher.finalize();
-                // println!("key: {:?}, result: {:?}, password: {:?}", form.key.as_bytes(), &result[..], decode_hex(password));
-                if &result[..] == pw {
-                    let string;
-                    let act = form.act.clone();
-                    if act == "style" {
-                        state.style_count.fetch_add(1, Ordering::Relaxed);
-                        string = "Style count increment";
-                    } else if act == "denounce" {
-                        *state.announcement.write().unwrap() = None;
-                        string = "Announcement disabled";
-                    } else if let Some(index) = form.act.find(' ') {
-                        *state.announcement.write().unwrap() =
-                            Some(form.act[index + 1..].to_string());
-                        string = "Announcement changed";
-                    } else {
-                        string = "Unknown command";
-                    }
-                    ran_command = RanState::RanCommand(string.to_string());
-                } else {
-                    ran_command = RanState::WrongPassword;
-                }
-            } else {
-                ran_command = RanState::PasswordNotHex;
-            }
-        } else {
-            error!(
-                state.lgr_important.borrow(),
-                "Unable to read password file for shell commands"
-            );
-            ran_command = RanState::WrongPassword;
-        }
-    } else {
-        ran_command = RanState::NoCommandToRun;
+    if index == 0 || index > videos.len() {
+        return HttpResponse::TemporaryRedirect()
+            .insert_header(("Location", format!("/playlist/{}/1", id)))
+            .finish();
     }
 
-    shell_render(ran_command, &form.key)
+    let position = PlaylistPosition {
+        previous: videos[(index + videos.len() - 2) % videos.len()].clone(),
+        next: videos[index % videos.len()].clone(),
+        id,
+        index,
+        len: videos.len(),
+    };
+    render_video(
+        &state,
+        &videos[index - 1],
+        PlayMode::Playlist,
+        Some(position),
+        &request,
+    )
 }
 
-async fn shell() -> impl Responder {
-    shell_render(RanState::NoCommandToRun, "")
+/// The videos of a playlist without those hidden since it was saved
+fn playlist_videos(state: &State, id: &str) -> Option<Vec<String>> {
+    let videos = state.playlists.read().unwrap().get(id)?.clone();
+    Some(
+        videos
+            .into_iter()
+            .filter(|video| !is_hidden(state, video))
+            .collect(),
+    )
 }
 
-fn shell_render(ran_command: RanState, key: &str) -> impl Responder {
+async fn show_playlist(state: web::Data<State>, path: web::Path<String>) -> impl Responder {
+    let videos = match playlist_videos(&state, &path) {
+        Some(videos) if !videos.is_empty() => videos,
+        _ => return HttpResponse::NotFound().body("No such playlist"),
+    };
+
     let html = html! {
         (DOCTYPE)
         html {
             head {
-                (header(0))
-                title { "Interactive Shell" }
+                (header(state.style_count.load(Ordering::Relaxed)))
+                title { "Playlist " (path) }
             }
             body {
-                p { "announce <whatever string here> - Excluding the < and > Will bring up a red bar on the main page with your announcement (Note that this string is NOT HTML escaped)" }
-                p { "denounce - Will remove the announcement" }
-                p { "style - Will increment the style counter so style updates are shown to users" }
-                form action="shell" method="POST" {
-                    input autofocus="" name="act" type="text" placeholder="Command" size="100";
-                    br;
-                    input name="key" type="password" placeholder="Key" value=(key);
-                    br;
-                    input type="submit" value="Submit";
+                (announcements::markup(&state, Target::All))
+                h4 { "Playlist " (path) " (" (videos.len()) " " (PLURALITY) ")" }
+                a href=(format!("/playlist/{}/1", path)) { "Play" }
+                ol {
+                    @for (index, video) in videos.iter().enumerate() {
+                        li { a href=(format!("/playlist/{}/{}", path, index + 1)) { (video) } }
+                    }
                 }
                 br;
                 a href="/" { "Return" }
-                br;
-                pre {
-                    @match ran_command {
-                        RanState::NoCommandToRun => {
-                            "No command run"
-                        }
-                        RanState::WrongPassword => {
-                            "Wrong password"
-                        }
-                        RanState::PasswordNotHex => {
-                            "Password is not in hex format on the server"
-                        }
-                        RanState::RanCommand(string) => {
-                            "Command executed:\n"
-                            pre class="feedback" {
-                                (&string)
-                            }
-                        }
-                    }
-                }
             }
         }
     };
     HttpResponse::Ok().body(html.into_string())
 }
 
-// ---
-
-async fn redirect_favicon() -> impl Responder {
-    HttpResponse::PermanentRedirect()
-        .insert_header(("Location", "/files/favicon/128.png"))
-        .finish()
+fn valid_playlist_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
 }
 
-async fn robots() -> impl Responder {
-    HttpResponse::PermanentRedirect()
-        .insert_header(("Location", "/files/misc/robots.txt"))
+/// Store a playlist on disk and in memory, returns the URL of the playlist
+fn save_playlist(
+    state: &State,
+    id: &str,
+    videos: Vec<String>,
+    actor: &Actor,
+) -> Result<String, String> {
+    if !valid_playlist_id(id) {
+        return Err("Playlist id must consist of letters, digits, '-' and '_'".to_string());
+    }
+    if videos.is_empty() {
+        return Err("Playlist contains no videos".to_string());
+    }
+    // Hidden videos are refused like missing ones, the playlist page would show their names
+    let missing = videos
+        .iter()
+        .find(|x| !state.video_info.read().unwrap().contains_key(*x) || is_hidden(state, x));
+    if let Some(missing) = missing {
+        return Err(format!("No such video: {}", missing));
+    }
+
+    let path: PathBuf = ["files", "playlists", id].iter().collect();
+    write_atomically(&path, &(videos.join("\n") + "\n")).map_err(|err| {
+        let message = format!("Unable to write playlist file: {}", err);
+        let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+        error!(state.lgr_important.borrow(), "Unable to write playlist file"; "error" => err, "user" => user, "request_id" => request_id);
+        message
+    })?;
+
+    state
+        .playlists
+        .write()
+        .unwrap()
+        .insert(id.to_string(), videos);
+
+    let (id, user, request_id) = (id.to_string(), actor.user.clone(), actor.request_id.clone());
+    info!(state.lgr_important.borrow(), "Playlist saved"; "id" => id, "user" => user, "request_id" => request_id; clone id);
+    Ok(format!("/playlist/{}", id))
+}
+
+fn delete_playlist(state: &State, id: &str, actor: &Actor) -> Result<(), String> {
+    if !valid_playlist_id(id) || state.playlists.write().unwrap().remove(id).is_none() {
+        return Err(format!("No such playlist: {}", id));
+    }
+    let path: PathBuf = ["files", "playlists", id].iter().collect();
+    std::fs::remove_file(path).map_err(|err| format!("Unable to remove playlist file: {}", err))?;
+
+    let (id, user, request_id) = (id.to_string(), actor.user.clone(), actor.request_id.clone());
+    info!(state.lgr_important.borrow(), "Playlist removed"; "id" => id, "user" => user, "request_id" => request_id);
+    Ok(())
+}
+
+#[derive(Clone, Deserialize)]
+struct PlaylistForm {
+    id: String,
+    csrf: String,
+    videos: String,
+}
+
+async fn create_playlist(
+    state: web::Data<State>,
+    form: web::Form<PlaylistForm>,
+    request: HttpRequest,
+    request_id: RequestId,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Editor) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    let videos: Vec<String> = form
+        .videos
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect();
+    let arguments = format!("{} {}", form.id, videos.join(" "));
+    let result = save_playlist(&state, &form.id, videos, &session.actor(&request_id));
+    audit::record(
+        &state,
+        audit::Entry::new(
+            &request,
+            &session.user.name,
+            &audit::route(&request),
+            &arguments,
+        )
+        .result(&result),
+    );
+    match result {
+        Ok(url) => HttpResponse::Created()
+            .insert_header(("Location", url.clone()))
+            .body(url),
+        Err(err) => HttpResponse::BadRequest().body(err),
+    }
+}
+
+/// Replace a file through a temporary file, so readers never see a partially written file
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.tmp", name));
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Set or clear (`None`) the source of a video, on disk and in memory
+fn set_source(
+    state: &State,
+    video: &str,
+    source: Option<String>,
+    actor: &Actor,
+) -> Result<String, String> {
+    if !state.video_info.read().unwrap().contains_key(video) {
+        return Err(format!("No such video: {}", video));
+    }
+    let path: PathBuf = ["files", "sources", video].iter().collect();
+    match &source {
+        Some(source) => write_atomically(&path, source),
+        None => std::fs::remove_file(&path).or_else(|err| match err.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        }),
+    }
+    .map_err(|err| {
+        let message = format!("Unable to write source file: {}", err);
+        let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+        error!(state.lgr_important.borrow(), "Unable to write source file"; "error" => err, "user" => user, "request_id" => request_id);
+        message
+    })?;
+
+    let previous = match state.video_info.write().unwrap().get_mut(video) {
+        Some(info) => std::mem::replace(&mut info.source, source.clone()),
+        None => return Err(format!("No such video: {}", video)),
+    };
+    refresh_list_page(state);
+
+    let message = match &source {
+        Some(_) => format!("Source of {} changed", video),
+        None => format!("Source of {} removed", video),
+    };
+    let (video, user, request_id) = (
+        video.to_string(),
+        actor.user.clone(),
+        actor.request_id.clone(),
+    );
+    info!(state.lgr_important.borrow(), "Source changed"; "video" => video, "previous" => InDebug(&previous), "source" => InDebug(&source), "user" => user, "request_id" => request_id; clone previous, source);
+    Ok(message)
+}
+
+fn set_visibility(
+    state: &State,
+    video: &str,
+    visibility: Visibility,
+    actor: &Actor,
+) -> Result<String, String> {
+    if !state.video_info.read().unwrap().contains_key(video) {
+        return Err(format!("No such video: {}", video));
+    }
+    let path: PathBuf = ["files", "visibility", video].iter().collect();
+    match visibility {
+        Visibility::Public => std::fs::remove_file(&path).or_else(|err| match err.kind() {
+            io::ErrorKind::NotFound => Ok(()),
+            _ => Err(err),
+        }),
+        _ => write_atomically(&path, visibility.as_str()),
+    }
+    .map_err(|err| {
+        let message = format!("Unable to write visibility file: {}", err);
+        let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+        error!(state.lgr_important.borrow(), "Unable to write visibility file"; "error" => err, "user" => user, "request_id" => request_id);
+        message
+    })?;
+
+    let previous = match state.video_info.write().unwrap().get_mut(video) {
+        Some(info) => std::mem::replace(&mut info.visibility, visibility),
+        None => return Err(format!("No such video: {}", video)),
+    };
+    recheck_todays_gondola(state);
+    refresh_list_page(state);
+
+    let (video, user, request_id) = (
+        video.to_string(),
+        actor.user.clone(),
+        actor.request_id.clone(),
+    );
+    let message = format!("{} is now {}", video, visibility.as_str());
+    info!(state.lgr_important.borrow(), "Visibility changed"; "video" => video, "previous" => previous.as_str(), "visibility" => visibility.as_str(), "user" => user, "request_id" => request_id);
+    Ok(message)
+}
+
+/// Only the views of public videos are shown, /metrics is not authenticated
+async fn show_metrics(state: web::Data<State>) -> impl Responder {
+    let video_infos = state.video_info.read().unwrap();
+    let metrics = state.metrics.render(
+        video_infos.len(),
+        video_infos
+            .iter()
+            .filter(|(_, info)| info.visibility == Visibility::Public)
+            .map(|(name, info)| (name.as_str(), info.views)),
+    );
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics)
+}
+
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().body("ok")
+}
+
+async fn readyz(state: web::Data<State>) -> impl Responder {
+    let mut failures = vec![];
+    if !state.health.loaded.load(Ordering::Relaxed) {
+        failures.push("initial load from disk has not finished");
+    }
+    if !state.health.updater_running.load(Ordering::Relaxed) {
+        failures.push("state-updater thread is not running");
+    }
+    if !state.health.last_rescan_ok.load(Ordering::Relaxed) {
+        failures.push("last rescan failed");
+    }
+    if state.video_info.is_poisoned() {
+        failures.push("video table lock is poisoned");
+    }
+    if read_dir("files/video/").is_err() {
+        failures.push("files/video is not readable");
+    }
+    // Every request probes its own file so concurrent checks do not remove each other's
+    let probe: PathBuf = [
+        "files",
+        "statistics",
+        &format!(".readyz-{:016x}", rand::thread_rng().gen::<u64>()),
+    ]
+    .iter()
+    .collect();
+    if File::create(&probe)
+        .and_then(|mut file| file.write_all(b"0"))
+        .and_then(|_| std::fs::remove_file(&probe))
+        .is_err()
+    {
+        failures.push("files/statistics is not writable");
+    }
+
+    if failures.is_empty() {
+        HttpResponse::Ok().body("ready")
+    } else {
+        HttpResponse::ServiceUnavailable().body(failures.join("\n"))
+    }
+}
+
+async fn unknown_route(
+    state: web::Data<State>,
+    request: HttpRequest,
+    request_id: RequestId,
+) -> impl Responder {
+    let request_string = privacy::redact_request(&request);
+    info!(state.lgr.borrow(), "Unknown route accessed"; "request" => request_string, "request_id" => request_id);
+    HttpResponse::TemporaryRedirect()
+        .insert_header(("Location", "/"))
         .finish()
 }
 
 // ---
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// Why an admin request was refused
+enum Refused {
+    WrongPassword,
+    BadPasswordFile,
+    Throttled(Duration),
+    NotLoggedIn,
+    BadCsrf,
+    Forbidden(Role),
+}
+
+impl Refused {
+    fn refusal(&self) -> (StatusCode, String) {
+        match self {
+            Refused::WrongPassword => (
+                StatusCode::UNAUTHORIZED,
+                "Wrong user or password".to_string(),
+            ),
+            Refused::BadPasswordFile => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Password file on the server is not a valid hash".to_string(),
+            ),
+            Refused::Throttled(wait) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "Too many wrong passwords, try again in {} seconds",
+                    wait.as_secs() + 1
+                ),
+            ),
+            Refused::NotLoggedIn => (
+                StatusCode::UNAUTHORIZED,
+                "Not logged in, log in at /login first".to_string(),
+            ),
+            Refused::BadCsrf => (
+                StatusCode::FORBIDDEN,
+                "The form is outdated or from another site, reload the page".to_string(),
+            ),
+            Refused::Forbidden(role) => (
+                StatusCode::FORBIDDEN,
+                format!("This needs the {} role", role),
+            ),
+        }
+    }
+
+    fn response(&self) -> HttpResponse {
+        let (status, message) = self.refusal();
+        HttpResponse::build(status).body(message)
+    }
+}
+
+/// Check a login against the users file, throttling clients that keep guessing. Argon2 is slow
+/// on purpose, so the password is checked on the blocking thread pool.
+async fn check_login(
+    state: &State,
+    name: &str,
+    password: &str,
+    request: &HttpRequest,
+) -> Result<User, Refused> {
+    let request_id = RequestId::of(request);
+    let client = auth::client(request);
+    if let Err(wait) = state.throttle.check(&client) {
+        let client = privacy::anonymize_ip(&client);
+        warn!(state.lgr_important.borrow(), "Password attempt while locked out"; "client" => client, "request_id" => request_id);
+        return Err(Refused::Throttled(wait));
+    }
+    let verified = {
+        let (name, password) = (name.to_string(), password.to_string());
+        web::block(move || users::verify(&name, &password))
+            .await
+            .unwrap_or_else(|err| Err(err.to_string()))
+    };
+    match verified {
+        Ok(Some(user)) => {
+            state.throttle.succeeded(&client);
+            if users::legacy_password_file() {
+                warn!(state.lgr_important.borrow(), "Password file holds a legacy SHA-512 hash, replace it with `gondola-rs hash-password`"; "request_id" => request_id);
+            }
+            Ok(user)
+        }
+        Ok(None) => {
+            let locked_out = state.throttle.failed(&client);
+            let (client, name) = (privacy::anonymize_ip(&client), name.to_string());
+            warn!(state.lgr_important.borrow(), "Wrong password"; "user" => name, "client" => client, "locked_out" => locked_out, "request_id" => request_id);
+            Err(Refused::WrongPassword)
+        }
+        Err(err) => {
+            error!(state.lgr_important.borrow(), "Unable to check the login"; "error" => err, "request_id" => request_id);
+            Err(Refused::BadPasswordFile)
+        }
+    }
+}
+
+/// The session of the admin making the request, if logged in. The role is looked up again so
+/// removing or demoting a user applies right away.
+fn session(state: &State, request: &HttpRequest) -> Option<Session> {
+    let cookie = request.cookie(SESSION_COOKIE)?;
+    let mut session = state.sessions.get(cookie.value())?;
+    session.user.role = users::role(&session.user.name)?;
+    Some(session)
+}
+
+/// Admin POSTs need a session with at least `role` and the session's CSRF token in the form.
+/// Refusals of logged in users are written to the audit log.
+fn authorize(
+    state: &State,
+    request: &HttpRequest,
+    csrf: &str,
+    role: Role,
+) -> Result<Session, Refused> {
+    let session = session(state, request).ok_or(Refused::NotLoggedIn)?;
+    let refused = if !session.csrf_matches(csrf) {
+        let (path, request_id) = (request.path().to_string(), RequestId::of(request));
+        warn!(state.lgr_important.borrow(), "Admin form with a wrong CSRF token"; "path" => path, "request_id" => request_id);
+        Refused::BadCsrf
+    } else if session.user.role < role {
+        Refused::Forbidden(role)
+    } else {
+        return Ok(session);
+    };
+    let entry = audit::Entry::new(request, &session.user.name, &audit::route(request), "");
+    audit::record(state, entry.denied(&refused.refusal().1));
+    Err(refused)
+}
+
+/// The session for an admin page at `path` needing `role`, or where to send the visitor instead
+fn page_session(
+    state: &State,
+    request: &HttpRequest,
+    path: &str,
+    role: Role,
+) -> Result<Session, HttpResponse> {
+    match session(state, request) {
+        Some(session) if session.user.role >= role => Ok(session),
+        Some(_) => Err(Refused::Forbidden(role).response()),
+        None => Err(login_redirect(path)),
+    }
+}
+
+/// Send an admin page visitor without a session to /login, which returns them to `path`
+fn login_redirect(path: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header(("Location", format!("/login?next={}", path)))
+        .finish()
+}
+
+fn session_cookie(value: &str, max_age: Duration) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, value.to_string())
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .secure(SESSION_COOKIE_SECURE)
+        .max_age(cookie::time::Duration::seconds(max_age.as_secs() as i64))
+        .finish()
+}
+
+#[derive(Clone, Default, Deserialize)]
+struct LoginForm {
+    #[serde(default)]
+    user: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    next: String,
+}
+
+/// Only return to paths on this site after logging in. Browsers drop tabs and newlines from URLs,
+/// so `/\t/evil.com` would be followed as `//evil.com`.
+fn local_path(next: &str) -> &str {
+    let local = !next.starts_with("//")
+        && !next
+            .chars()
+            .any(|x| x.is_control() || x.is_whitespace() || x == '\\')
+        && next.parse::<Uri>().map_or(false, |uri| {
+            uri.scheme().is_none() && uri.authority().is_none() && uri.path().starts_with('/')
+        });
+    if local {
+        next
+    } else {
+        "/shell"
+    }
+}
+
+async fn show_login(state: web::Data<State>, query: web::Query<LoginForm>) -> impl Responder {
+    login_render(&state, StatusCode::OK, local_path(&query.next), None)
+}
+
+async fn login(
+    state: web::Data<State>,
+    form: web::Form<LoginForm>,
+    request: HttpRequest,
+) -> impl Responder {
+    let next = local_path(&form.next);
+    match check_login(&state, form.user.trim(), &form.password, &request).await {
+        Ok(user) => {
+            let (name, request_id) = (user.name.clone(), RequestId::of(&request));
+            audit::record(&state, audit::Entry::new(&request, &name, "login", ""));
+            let cookie = state.sessions.create(user);
+            info!(state.lgr_important.borrow(), "Logged in"; "user" => name, "request_id" => request_id);
+            HttpResponse::SeeOther()
+                .cookie(session_cookie(&cookie, SESSION_LIFETIME))
+                .insert_header(("Location", next))
+                .finish()
+        }
+        Err(err) => {
+            let (status, message) = err.refusal();
+            let entry = audit::Entry::new(&request, form.user.trim(), "login", "");
+            match err {
+                Refused::BadPasswordFile => {
+                    audit::record(&state, entry.result(&Err(message.clone())))
+                }
+                // Already in the important log, and the audit log would grow with every attempt
+                Refused::Throttled(_) => {}
+                _ => audit::record(&state, entry.denied(&message)),
+            }
+            login_render(&state, status, next, Some(message))
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+struct CsrfForm {
+    csrf: String,
+}
+
+async fn logout(
+    state: web::Data<State>,
+    form: web::Form<CsrfForm>,
+    request: HttpRequest,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Announcer) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    audit::record(
+        &state,
+        audit::Entry::new(&request, &session.user.name, "logout", ""),
+    );
+    if let Some(cookie) = request.cookie(SESSION_COOKIE) {
+        state.sessions.remove(cookie.value());
+    }
+    HttpResponse::SeeOther()
+        .cookie(session_cookie("", Duration::ZERO))
+        .insert_header(("Location", "/login"))
+        .finish()
+}
+
+fn login_render(
+    state: &State,
+    status: StatusCode,
+    next: &str,
+    message: Option<String>,
+) -> HttpResponse {
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(state.style_count.load(Ordering::Relaxed)))
+                title { "Log in" }
+            }
+            body {
+                (announcements::markup(state, Target::All))
+                form action="/login" method="POST" {
+                    input autofocus="" name="user" type="text" placeholder="User";
+                    br;
+                    input name="password" type="password" placeholder="Password";
+                    input name="next" type="hidden" value=(next);
+                    br;
+                    input type="submit" value="Log in";
+                }
+                @if let Some(message) = message {
+                    pre class="feedback" { (message) }
+                }
+            }
+        }
+    };
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(html.into_string())
+}
+
+/// Logs the admin out, shown on every admin page
+fn logout_form(session: &Session) -> Markup {
+    html! {
+        form action="/logout" method="POST" {
+            (session.user.name) " (" (session.user.role) ") "
+            input name="csrf" type="hidden" value=(session.csrf);
+            input type="submit" value="Log out";
+        }
+    }
+}
+
+// ---
+
+#[derive(Clone, Deserialize)]
+struct ShellCommandForm {
+    act: String,
+    csrf: String,
+}
+
+async fn do_shell(
+    state: web::Data<State>,
+    form: web::Form<ShellCommandForm>,
+    request: HttpRequest,
+    request_id: RequestId,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Announcer) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    let mut output = None;
+    if !form.act.is_empty() {
+        let command = form
+            .act
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let user = session.user.name.clone();
+        info!(state.lgr.borrow(), "Running shell"; "command" => command, "user" => user, "request_id" => request_id; clone request_id);
+        // rescan waits for the updater and most commands write files, keep them off the async workers
+        let result = {
+            let state = (**state).clone();
+            let line = form.act.clone();
+            let user = session.user.clone();
+            web::block(move || shell::run(&state, &line, &user, &request_id))
+                .await
+                .unwrap_or_else(|err| Err(err.to_string()))
+        };
+        shell::record(&state, &form.act, &session.user, &result);
+        shell::audit(&state, &request, &form.act, &session.user, &result);
+        output = Some(result.unwrap_or_else(|err| format!("Error: {}", err)));
+    }
+    shell_render(&state, &session, output)
+}
+
+async fn shell(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    match page_session(&state, &request, "/shell", Role::Announcer) {
+        Ok(session) => shell_render(&state, &session, None),
+        Err(response) => response,
+    }
+}
+
+fn shell_render(state: &State, session: &Session, output: Option<String>) -> HttpResponse {
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(0))
+                title { "Interactive Shell" }
+            }
+            body {
+                (announcements::markup(state, Target::All))
+                @for command in shell::COMMANDS.iter().filter(|x| x.role <= session.user.role) {
+                    p { (command.usage()) " - " (command.help) }
+                }
+                p { "Arguments containing spaces can be quoted with \"double\" or 'single' quotes" }
+                form action="shell" method="POST" {
+                    input autofocus="" name="act" type="text" placeholder="Command" size="100";
+                    input name="csrf" type="hidden" value=(session.csrf);
+                    br;
+                    input type="submit" value="Submit";
+                }
+                br;
+                a href="/" { "Return" }
+                " "
+                a href="/logs" { "Logs" }
+                " "
+                a href="/audit" { "Audit" }
+                " "
+                a href="/upload" { "Upload" }
+                " "
+                a href="/admin" { "Dashboard" }
+                (logout_form(session))
+                pre {
+                    @match output {
+                        None => {
+                            "No command run"
+                        }
+                        Some(string) => {
+                            "Command executed:\n"
+                            pre class="feedback" {
+                                (&string)
+                            }
+                        }
+                    }
+                }
+                h4 { "History (most recent first)" }
+                @for entry in state.shell_history.read().unwrap().iter().rev().filter(|x| x.is_visible_to(&session.user)) {
+                    pre {
+                        (entry.time.format("%Y-%m-%d %H:%M:%S")) " " (entry.user) " > " (entry.line) "\n"
+                        @match &entry.result {
+                            Ok(output) => (output),
+                            Err(err) => "Error: " (err),
+                        }
+                    }
+                }
+            }
+        }
+    };
+    HttpResponse::Ok().body(html.into_string())
+}
+
+// ---
+
+#[derive(Clone, Default, Deserialize)]
+struct LogsForm {
+    csrf: String,
+    log: String,
+    level: String,
+    context: String,
+    contains: String,
+    from: String,
+    to: String,
+}
+
+async fn show_logs(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    match page_session(&state, &request, "/logs", Role::Admin) {
+        Ok(session) => logs_render(&state, &session, &LogsForm::default(), Ok(vec![])),
+        Err(response) => response,
+    }
+}
+
+async fn search_logs(
+    state: web::Data<State>,
+    form: web::Form<LogsForm>,
+    request: HttpRequest,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Admin) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    let path = match form.log.as_str() {
+        "important" => "files/logs/important",
+        _ => "files/logs/log",
+    };
+    let time = |x: &str| NaiveDateTime::parse_from_str(x, "%Y-%m-%dT%H:%M").ok();
+    let filter = logs::Filter {
+        level: logs::parse_level(&form.level),
+        context: form.context.clone(),
+        contains: form.contains.clone(),
+        from: time(&form.from),
+        to: time(&form.to),
+    };
+    let result = match web::block(move || logs::search(path, &filter, LOG_VIEWER_LINES)).await {
+        Ok(Ok(lines)) => Ok(lines),
+        Ok(Err(err)) => Err(format!("Unable to read the logs: {}", err)),
+        Err(err) => Err(format!("Unable to read the logs: {}", err)),
+    };
+    logs_render(&state, &session, &form, result)
+}
+
+fn logs_render(
+    state: &State,
+    session: &Session,
+    form: &LogsForm,
+    result: Result<Vec<String>, String>,
+) -> HttpResponse {
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(0))
+                title { "Logs" }
+            }
+            body {
+                (announcements::markup(state, Target::All))
+                form action="logs" method="POST" {
+                    select name="log" {
+                        option value="log" selected[form.log != "important"] { "site" }
+                        option value="important" selected[form.log == "important"] { "important" }
+                    }
+                    " "
+                    select name="level" {
+                        option value="" { "any level" }
+                        @for (name, _) in logs::LEVELS.iter() {
+                            option value=(name) selected[form.level == *name] { (name) " and above" }
+                        }
+                    }
+                    " "
+                    input name="context" type="text" placeholder="Context" value=(form.context);
+                    " "
+                    input name="contains" type="text" placeholder="Contains" value=(form.contains) size="40";
+                    br;
+                    "From (UTC) "
+                    input name="from" type="datetime-local" value=(form.from);
+                    " to "
+                    input name="to" type="datetime-local" value=(form.to);
+                    br;
+                    input name="csrf" type="hidden" value=(session.csrf);
+                    br;
+                    input type="submit" value="Search";
+                }
+                br;
+                a href="/shell" { "Shell" }
+                " "
+                a href="/audit" { "Audit" }
+                (logout_form(session))
+                br;
+                @match result {
+                    Ok(lines) => {
+                        p { (lines.len()) " lines (at most " (LOG_VIEWER_LINES) ", newest last)" }
+                        pre {
+                            @for line in lines {
+                                (line) "\n"
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        pre { (err) }
+                    }
+                }
+            }
+        }
+    };
+    HttpResponse::Ok().body(html.into_string())
+}
+
+// ---
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+struct AuditForm {
+    csrf: String,
+    user: String,
+    action: String,
+    outcome: String,
+    contains: String,
+    from: String,
+    to: String,
+}
+
+async fn show_audit(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    match page_session(&state, &request, "/audit", Role::Admin) {
+        Ok(session) => {
+            let result = search_audit_log(audit::Filter::default()).await;
+            audit_render(&state, &session, &AuditForm::default(), result)
+        }
+        Err(response) => response,
+    }
+}
+
+async fn search_audit(
+    state: web::Data<State>,
+    form: web::Form<AuditForm>,
+    request: HttpRequest,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Admin) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    let time = |x: &str| {
+        NaiveDateTime::parse_from_str(x, "%Y-%m-%dT%H:%M")
+            .ok()
+            .map(|x| x.and_utc())
+    };
+    let filter = audit::Filter {
+        user: form.user.trim().to_string(),
+        action: form.action.trim().to_string(),
+        outcome: audit::Outcome::parse(&form.outcome),
+        contains: form.contains.clone(),
+        from: time(&form.from),
+        to: time(&form.to),
+    };
+    let result = search_audit_log(filter).await;
+    audit_render(&state, &session, &form, result)
+}
+
+async fn search_audit_log(filter: audit::Filter) -> Result<Vec<audit::Entry>, String> {
+    match web::block(move || audit::search(&filter, AUDIT_VIEWER_ENTRIES)).await {
+        Ok(Ok(entries)) => Ok(entries),
+        Ok(Err(err)) => Err(format!("Unable to read the audit log: {}", err)),
+        Err(err) => Err(format!("Unable to read the audit log: {}", err)),
+    }
+}
+
+fn audit_render(
+    state: &State,
+    session: &Session,
+    form: &AuditForm,
+    result: Result<Vec<audit::Entry>, String>,
+) -> HttpResponse {
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(0))
+                title { "Audit" }
+            }
+            body {
+                (announcements::markup(state, Target::All))
+                form action="audit" method="POST" {
+                    input name="user" type="text" placeholder="User" value=(form.user);
+                    " "
+                    input name="action" type="text" placeholder="Action" value=(form.action);
+                    " "
+                    select name="outcome" {
+                        option value="" { "any outcome" }
+                        @for outcome in [audit::Outcome::Ok, audit::Outcome::Error, audit::Outcome::Denied] {
+                            option value=(outcome.as_str()) selected[form.outcome == outcome.as_str()] { (outcome.as_str()) }
+                        }
+                    }
+                    " "
+                    input name="contains" type="text" placeholder="Contains" value=(form.contains) size="40";
+                    br;
+                    "From (UTC) "
+                    input name="from" type="datetime-local" value=(form.from);
+                    " to "
+                    input name="to" type="datetime-local" value=(form.to);
+                    br;
+                    input name="csrf" type="hidden" value=(session.csrf);
+                    br;
+                    input type="submit" value="Search";
+                }
+                br;
+                a href="/shell" { "Shell" }
+                " "
+                a href="/logs" { "Logs" }
+                (logout_form(session))
+                br;
+                @match result {
+                    Ok(entries) => {
+                        p { (entries.len()) " entries (at most " (AUDIT_VIEWER_ENTRIES) ", newest first)" }
+                        table {
+                            tr {
+                                th { "Time (UTC)" }
+                                th { "User" }
+                                th { "Client" }
+                                th { "Action" }
+                                th { "Arguments" }
+                                th { "Outcome" }
+                                th { "Message" }
+                                th { "Request ID" }
+                            }
+                            @for entry in entries {
+                                tr {
+                                    td { (entry.time.format("%Y-%m-%d %H:%M:%S")) }
+                                    td { (entry.user) }
+                                    td { (entry.client) }
+                                    td { (entry.action) }
+                                    td { (entry.arguments) }
+                                    td { (entry.outcome.as_str()) }
+                                    td { pre { (entry.message) } }
+                                    td { code { (entry.request_id) } }
+                                }
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        pre { (err) }
+                    }
+                }
+            }
+        }
+    };
+    HttpResponse::Ok().body(html.into_string())
+}
+
+// ---
+
+async fn redirect_favicon() -> impl Responder {
+    HttpResponse::PermanentRedirect()
+        .insert_header(("Location", "/files/favicon/128.png"))
+        .finish()
+}
+
+async fn robots() -> impl Responder {
+    HttpResponse::PermanentRedirect()
+        .insert_header(("Location", "/files/misc/robots.txt"))
+        .finish()
+}
+
+// ---
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 enum PlayMode {
+    #[default]
     Random,
     Sequential,
+    Playlist,
 }
 
-impl Default for PlayMode {
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Visibility {
+    /// Listed, played at random and in order
+    #[default]
+    Public,
+    /// Only reachable through its own URL or a playlist
+    Unlisted,
+    /// Not served at all
+    Hidden,
+}
+
+impl Visibility {
+    fn as_str(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Hidden => "hidden",
+        }
+    }
+
+    fn parse(visibility: &str) -> Option<Self> {
+        match visibility {
+            "public" => Some(Visibility::Public),
+            "unlisted" => Some(Visibility::Unlisted),
+            "hidden" => Some(Visibility::Hidden),
+            _ => None,
+        }
+    }
+
+    /// Read from `files/visibility/{name}`, videos without the file are public
+    fn read(name: &str) -> Self {
+        let path: PathBuf = ["files", "visibility", name].iter().collect();
+        Self::parse(slurp(&path).unwrap_or_default().trim()).unwrap_or_default()
+    }
+}
+
+/// The list page as last generated, compressed once and shared by the requests without copying it
+#[derive(Clone)]
+struct ListPage {
+    /// Without announcements, which are put at `slot`
+    variants: Variants,
+    slot: usize,
+    etag: String,
+    generated: SystemTime,
+    /// The page with the announcements shown at the moment and its ETag, compressed on the first
+    /// request after they change
+    announced: Arc<Mutex<Option<(String, Variants)>>>,
+}
+
+impl Default for ListPage {
     fn default() -> Self {
-        PlayMode::Random
+        Self {
+            variants: Variants::default(),
+            slot: 0,
+            etag: caching::etag(b""),
+            generated: SystemTime::UNIX_EPOCH,
+            announced: Arc::default(),
+        }
     }
 }
 
+#[derive(Default)]
+struct Health {
+    pub loaded: AtomicBool,
+    pub updater_running: AtomicBool,
+    pub last_rescan_ok: AtomicBool,
+}
+
 #[derive(Clone)]
 struct State {
-    pub announcement: Arc<RwLock<Option<String>>>,
+    pub access_log: AccessLog,
+    pub announcements: Arc<RwLock<Vec<announcements::Announcement>>>,
+    pub health: Arc<Health>,
+    pub history: Arc<RwLock<History>>,
     pub style_count: Arc<AtomicU64>,
+    /// The gondolas of the day within `TODAY_NO_REPEAT_DAYS`, today's last
+    pub today: Arc<RwLock<VecDeque<(NaiveDate, String)>>>,
     pub lgr: RefCell<Logger<Generic>>,
     pub lgr_important: RefCell<Logger<Generic>>,
-    pub listpage: Arc<RwLock<String>>,
+    pub listpage: Arc<RwLock<ListPage>>,
+    pub metrics: Arc<Metrics>,
+    pub playlists: Arc<RwLock<IndexMap<String, Vec<String>>>>,
     pub random: RefCell<Random>,
     pub random_counter: Arc<AtomicU64>,
+    /// Wakes up the state updater, replaced in main once the updater is running
+    pub updates: mpsc::Sender<Update>,
+    pub sessions: Arc<Sessions>,
+    pub shell_history: Arc<RwLock<VecDeque<shell::HistoryEntry>>>,
+    pub throttle: Arc<auth::Throttle>,
+    /// Wrong API tokens, counted apart from wrong passwords
+    pub token_throttle: Arc<auth::Throttle>,
     pub video_info: Arc<RwLock<IndexMap<String, VideoInfo>>>,
+    /// For the uptime on /admin
+    pub started: Instant,
 }
 
 impl Default for State {
     fn default() -> Self {
-        let lgr =
-            Logger::spawn_with_writer("site", writer::create_rotational_writer("files/logs/log"));
+        let metrics = Arc::new(Metrics::default());
+        let lgr = Logger::spawn_with_writer(
+            "site",
+            writer::create_writer(
+                "files/logs/log",
+                &LOG_SITE,
+                metrics.log_write_errors.clone(),
+            ),
+        );
         let lgr_important = Logger::spawn_with_writer(
             "important",
-            writer::create_rotational_writer("files/logs/important"),
+            writer::create_writer(
+                "files/logs/important",
+                &LOG_IMPORTANT,
+                metrics.log_write_errors.clone(),
+            ),
         );
         lgr.set_colorize(true);
         lgr.set_log_level(LOGLEVEL);
         lgr_important.set_colorize(true);
         lgr_important.set_log_level(LOGLEVEL_IMPORTANT);
+        let access_log = AccessLog::spawn(
+            ACCESS_LOG_FORMAT,
+            writer::create_writer(
+                "files/logs/access",
+                &LOG_ACCESS,
+                metrics.log_write_errors.clone(),
+            ),
+        );
         Self {
-            announcement: Arc::new(RwLock::new(None)),
+            access_log,
+            announcements: Arc::new(RwLock::new(Vec::new())),
+            health: Arc::new(Health::default()),
+            history: Arc::new(RwLock::new(History::default())),
             style_count: Arc::new(AtomicU64::new(0)),
+            today: Arc::new(RwLock::new(VecDeque::new())),
             lgr: RefCell::new(lgr),
             lgr_important: RefCell::new(lgr_important),
-            listpage: Arc::new(RwLock::new(String::new())),
+            listpage: Arc::new(RwLock::new(ListPage::default())),
+            metrics,
+            playlists: Arc::new(RwLock::new(IndexMap::new())),
             random: RefCell::new(Random::new(0)),
             random_counter: Arc::new(AtomicU64::new(0)),
+            updates: mpsc::channel().0,
+            sessions: Arc::new(Sessions::default()),
+            shell_history: Arc::new(RwLock::new(VecDeque::new())),
+            throttle: Arc::new(auth::Throttle::default()),
+            token_throttle: Arc::new(auth::Throttle::per_client()),
             video_info: Arc::new(RwLock::new(IndexMap::new())),
+            started: Instant::now(),
         }
     }
 }
@@ -744,6 +2238,7 @@ struct VideoInfo {
     pub added: SystemTime,
     pub source: Option<String>,
     pub views: usize,
+    pub visibility: Visibility,
 }
 
 impl Default for VideoInfo {
@@ -752,6 +2247,7 @@ impl Default for VideoInfo {
             added: SystemTime::UNIX_EPOCH,
             source: Option::default(),
             views: usize::default(),
+            visibility: Visibility::default(),
         }
     }
 }
@@ -795,6 +2291,7 @@ fn read_state_from_disk(state: &mut State) -> io::Result<()> {
                     added: modified,
                     source: slurp(&source).ok(),
                     views,
+                    visibility: Visibility::read(filename),
                 };
 
                 video_infos.insert(filename.into(), video_info.clone());
@@ -810,113 +2307,458 @@ fn read_state_from_disk(state: &mut State) -> io::Result<()> {
     }
 
     video_infos.sort_keys();
+
+    match load_todays_gondolas() {
+        Ok(picks) => *state.today.write().unwrap() = picks,
+        Err(err) => {
+            error!(lgr_important, "Unable to load the gondolas of the day"; "error" => err);
+        }
+    }
+
+    match announcements::load() {
+        Ok(announcements) => *state.announcements.write().unwrap() = announcements,
+        Err(err) => {
+            error!(lgr_important, "Unable to load the announcements"; "error" => err);
+        }
+    }
+
+    match read_dir("files/playlists/") {
+        Ok(directory) => {
+            let mut playlists = state.playlists.write().unwrap();
+            for file in directory {
+                let path = match file {
+                    Ok(file) => file.path(),
+                    Err(err) => {
+                        warn!(lgr_important, "Unable to read directory entry"; "directory" => "files/playlists", "error" => err);
+                        continue;
+                    }
+                };
+                if let Some(Some(id)) = path.file_name().map(|x| x.to_str()) {
+                    if id.starts_with('.') {
+                        continue;
+                    }
+                    match slurp(&path) {
+                        Ok(contents) => {
+                            let videos = contents
+                                .lines()
+                                .map(str::trim)
+                                .filter(|x| !x.is_empty())
+                                .map(String::from)
+                                .collect();
+                            playlists.insert(id.into(), videos);
+                        }
+                        Err(err) => {
+                            warn!(lgr_important, "Unable to read playlist, skipping it"; "filename" => InDebug(&path), "error" => err);
+                        }
+                    }
+                } else {
+                    warn!(lgr_important, "Unable to read file name from file"; "filename" => InDebug(&path));
+                }
+            }
+        }
+        Err(err) => {
+            warn!(lgr_important, "Unable to read directory"; "directory" => "files/playlists", "error" => err);
+        }
+    }
+
     Ok(())
 }
 
-fn update_state(mut state: State) {
-    let lgr = state.lgr.borrow().clone_with_context("state-updater");
-    let lgr_important = state.lgr_important.borrow().clone_add_context("important");
-    loop {
-        thread::sleep(Duration::from_secs(60 * 30));
-        {
-            benchmark! {
-                |duration| info!(lgr, "Time to load video files and sources"; "duration" => InDebug(&duration)),
-                match read_dir("files/video/") {
-                    Ok(directory) => {
-                        for file in directory {
+#[derive(Serialize)]
+struct Timing {
+    step: &'static str,
+    seconds: f64,
+}
 
-                            let file = if let Ok(file) = file { file } else { continue };
-                            let path = file.path();
+/// Outcome of a scan of the video directory
+#[derive(Default, Serialize)]
+struct Rescan {
+    added: Vec<String>,
+    changed: Vec<String>,
+    removed: Vec<String>,
+    timings: Vec<Timing>,
+    success: bool,
+}
 
-                            if let Some(Some(filename)) = path.file_name().map(|x| x.to_str()) {
+impl Rescan {
+    fn summary(&self) -> String {
+        let mut out = format!(
+            "Rescan {}: {} added, {} changed, {} removed",
+            if self.success {
+                "finished"
+            } else {
+                "had errors"
+            },
+            self.added.len(),
+            self.changed.len(),
+            self.removed.len()
+        );
+        for (label, names) in [
+            ("Added", &self.added),
+            ("Changed", &self.changed),
+            ("Removed", &self.removed),
+        ] {
+            if !names.is_empty() {
+                out += &format!("\n{}: {}", label, names.join(", "));
+            }
+        }
+        for timing in &self.timings {
+            out += &format!("\n{}: {:.3}s", timing.step, timing.seconds);
+        }
+        out
+    }
+}
 
-                                if filename.starts_with('.') {
-                                    continue;
-                                }
+/// Pick up new, changed and removed videos and sources, flush view counts to disk and regenerate
+/// the list page
+fn rescan(state: &State, lgr: &Logger<Generic>, lgr_important: &Logger<Generic>) -> Rescan {
+    let rescan_start = Instant::now();
+    let mut result = Rescan {
+        success: true,
+        ..Rescan::default()
+    };
+    let mut timings = vec![];
+    {
+        benchmark! {
+            |duration| {
+                info!(lgr, "Time to load video files and sources"; "duration" => InDebug(&duration));
+                timings.push(("Load video files and sources", duration));
+            },
+            match read_dir("files/video/") {
+                Ok(directory) => {
+                    let mut seen = vec![];
+                    for file in directory {
+
+                        let file = if let Ok(file) = file { file } else { continue };
+                        let path = file.path();
+
+                        if let Some(Some(filename)) = path.file_name().map(|x| x.to_str()) {
+
+                            if filename.starts_with('.') {
+                                continue;
+                            }
+                            seen.push(filename.to_string());
 
-                                let modified = if let Ok(metadata) = file.metadata() {
-                                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)
-                                } else {
-                                    SystemTime::UNIX_EPOCH
-                                };
-
-                                let source: PathBuf = ["files", "sources", filename].iter().collect();
-
-                                let video_info = VideoInfo {
-                                    added: modified,
-                                    source: slurp(&source).ok(),
-                                    views: 0,
-                                };
-
-                                {
-                                    let mut writer = state.video_info.write().unwrap();
-                                    if let Some(entry) = writer.get_mut(filename) {
-                                        entry.added = video_info.added;
-                                        entry.source = video_info.source;
-                                    } else {
-                                        writer.insert(filename.into(), video_info.clone());
-
-                                        let filename = String::from(filename);
-                                        trace!(lgr, "Inserting new file into table"; "filename" => filename, "info" => InDebug(&video_info); clone video_info);
+                            let modified = if let Ok(metadata) = file.metadata() {
+                                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)
+                            } else {
+                                SystemTime::UNIX_EPOCH
+                            };
+
+                            let source: PathBuf = ["files", "sources", filename].iter().collect();
+
+                            let video_info = VideoInfo {
+                                added: modified,
+                                source: slurp(&source).ok(),
+                                views: 0,
+                                visibility: Visibility::read(filename),
+                            };
+
+                            {
+                                let mut writer = state.video_info.write().unwrap();
+                                if let Some(entry) = writer.get_mut(filename) {
+                                    if entry.added != video_info.added
+                                        || entry.source != video_info.source
+                                        || entry.visibility != video_info.visibility
+                                    {
+                                        result.changed.push(filename.to_string());
                                     }
+                                    entry.added = video_info.added;
+                                    entry.source = video_info.source;
+                                    entry.visibility = video_info.visibility;
+                                } else {
+                                    writer.insert(filename.into(), video_info.clone());
+                                    result.added.push(filename.to_string());
+
+                                    let filename = String::from(filename);
+                                    trace!(lgr, "Inserting new file into table"; "filename" => filename, "info" => InDebug(&video_info); clone video_info);
                                 }
-                            } else {
-                                error!(lgr_important, "Unable to read file name from file"; "filename" => InDebug(&path));
                             }
+                        } else {
+                            error!(lgr_important, "Unable to read file name from file"; "filename" => InDebug(&path));
                         }
                     }
-                    Err(err) => {
-                        error!(lgr_important, "Unable to read directory"; "directory" => "files/video", "error" => err);
+
+                    let mut writer = state.video_info.write().unwrap();
+                    writer.retain(|name, _| {
+                        let keep = seen.contains(name);
+                        if !keep {
+                            result.removed.push(name.clone());
+                        }
+                        keep
+                    });
+                    if !result.added.is_empty() {
+                        writer.sort_keys();
                     }
                 }
+                Err(err) => {
+                    result.success = false;
+                    error!(lgr_important, "Unable to read directory"; "directory" => "files/video", "error" => err);
+                }
             }
+        }
 
-            let video_infos = benchmark! {
-                |duration| info!(lgr, "Time to copy table"; "duration" => InDebug(&duration)),
-                state.video_info.read().unwrap().clone()
-            };
+        if !result.removed.is_empty() || !result.changed.is_empty() {
+            recheck_todays_gondola(state);
+        }
 
-            benchmark! {
-                |duration| info!(lgr, "Time to write statistics to disk"; "duration" => InDebug(&duration)),
-                for (key, value) in video_infos.iter() {
-                    let views: PathBuf = ["files", "statistics", &key].iter().collect();
-                    match File::create(views) {
-                        Ok(mut file) => {
-                            match file.write_all(value.views.to_string().as_bytes()) {
-                                Ok(_) => {}
-                                Err(err) => {
-                                    error!(lgr_important, "Unable to write to statistics file"; "error" => err);
-                                }
+        let video_infos = benchmark! {
+            |duration| {
+                info!(lgr, "Time to copy table"; "duration" => InDebug(&duration));
+                timings.push(("Copy table", duration));
+            },
+            state.video_info.read().unwrap().clone()
+        };
+
+        benchmark! {
+            |duration| {
+                info!(lgr, "Time to write statistics to disk"; "duration" => InDebug(&duration));
+                timings.push(("Write statistics to disk", duration));
+            },
+            for (key, value) in video_infos.iter() {
+                let views: PathBuf = ["files", "statistics", key].iter().collect();
+                match File::create(views) {
+                    Ok(mut file) => {
+                        match file.write_all(value.views.to_string().as_bytes()) {
+                            Ok(_) => {}
+                            Err(err) => {
+                                result.success = false;
+                                error!(lgr_important, "Unable to write to statistics file"; "error" => err);
                             }
                         }
-                        Err(err) => {
-                            error!(lgr_important, "Unable to create statistics file"; "error" => err);
-                        }
                     }
-
+                    Err(err) => {
+                        result.success = false;
+                        error!(lgr_important, "Unable to create statistics file"; "error" => err);
+                    }
                 }
+
+            }
+        }
+    }
+    let list_start = Instant::now();
+    generate_list_page(state);
+    timings.push(("Generate list page", list_start.elapsed()));
+    timings.push(("Total", rescan_start.elapsed()));
+    state
+        .metrics
+        .observe_rescan(rescan_start.elapsed(), result.success);
+    state
+        .health
+        .last_rescan_ok
+        .store(result.success, Ordering::Relaxed);
+
+    result.timings = timings
+        .into_iter()
+        .map(|(step, duration)| Timing {
+            step,
+            seconds: duration.as_secs_f64(),
+        })
+        .collect();
+    result
+}
+
+/// Time until the next day (UTC) starts, when the list page shows a new gondola of the day
+fn until_tomorrow() -> Duration {
+    let now = Utc::now();
+    let tomorrow = now
+        .date_naive()
+        .succ_opt()
+        .and_then(|x| x.and_hms_opt(0, 0, 0))
+        .map(|x| x.and_utc());
+    let wait = tomorrow.and_then(|x| (x - now).to_std().ok());
+    wait.unwrap_or(RESCAN_INTERVAL) + Duration::from_secs(1)
+}
+
+/// What the state updater thread is asked to do
+enum Update {
+    /// Rescan now and send back the result
+    Rescan(mpsc::Sender<Rescan>),
+    /// Generate the list page again after a source, visibility or upload changed it
+    ListPage,
+}
+
+/// Rescans every `RESCAN_INTERVAL` and when a new day starts, and does what it is asked to through
+/// `updates` in between
+fn update_state(state: State, updates: mpsc::Receiver<Update>) {
+    let lgr = state.lgr.borrow().clone_with_context("state-updater");
+    let lgr_important = state.lgr_important.borrow().clone_add_context("important");
+    let next_rescan = || Instant::now() + cmp::min(RESCAN_INTERVAL, until_tomorrow());
+    let mut rescan_at = next_rescan();
+    loop {
+        let timeout = rescan_at.saturating_duration_since(Instant::now());
+        let reply = match updates.recv_timeout(timeout) {
+            Ok(Update::ListPage) => {
+                generate_list_page(&state);
+                continue;
+            }
+            Ok(Update::Rescan(reply)) => Some(reply),
+            Err(mpsc::RecvTimeoutError::Timeout) => None,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                thread::sleep(timeout);
+                None
             }
+        };
+        let result = rescan(&state, &lgr, &lgr_important);
+        rescan_at = next_rescan();
+        if let Some(reply) = reply {
+            let _ = reply.send(result);
+        }
+    }
+}
+
+/// Generate the list page on the updater thread, since compressing it takes long. Done right
+/// here when the updater is not running.
+fn refresh_list_page(state: &State) {
+    if state.updates.send(Update::ListPage).is_err() {
+        generate_list_page(state);
+    }
+}
+
+/// Ask the updater thread to rescan now and wait until it is done
+fn request_rescan(updates: &mpsc::Sender<Update>) -> Result<Rescan, String> {
+    let (reply, result) = mpsc::channel();
+    updates
+        .send(Update::Rescan(reply))
+        .map_err(|_| "The state updater is not running".to_string())?;
+    result
+        .recv()
+        .map_err(|_| "The state updater stopped during the rescan".to_string())
+}
+
+async fn api_rescan(
+    state: web::Data<State>,
+    form: web::Form<CsrfForm>,
+    request: HttpRequest,
+) -> impl Responder {
+    let session = match authorize(&state, &request, &form.csrf, Role::Editor) {
+        Ok(session) => session,
+        Err(err) => return err.response(),
+    };
+    let updates = state.updates.clone();
+    let result = web::block(move || request_rescan(&updates)).await;
+    let entry = audit::Entry::new(&request, &session.user.name, &audit::route(&request), "");
+    let summary = match &result {
+        Ok(Ok(rescan)) => Ok(rescan.summary()),
+        Ok(Err(err)) => Err(err.clone()),
+        Err(err) => Err(err.to_string()),
+    };
+    audit::record(&state, entry.result(&summary));
+    match result {
+        Ok(Ok(result)) => HttpResponse::Ok().json(result),
+        Ok(Err(err)) => HttpResponse::ServiceUnavailable().body(err),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
+
+/// Read a password from standard input for the subcommands
+fn read_password() -> io::Result<String> {
+    eprintln!("Password:");
+    let mut password = String::new();
+    io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The password is empty",
+        ));
+    }
+    Ok(password.to_string())
+}
+
+/// Subcommands that manage the credentials instead of starting the server, `None` starts it
+fn subcommand(args: &[String]) -> Option<io::Result<()>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        // Stores the hash in the `password` file, used when there is no users file
+        ["hash-password"] => read_password().and_then(|password| {
+            let hash =
+                auth::hash(&password).map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            write_atomically(Path::new("password"), &hash)
+        }),
+        ["add-user", name, role] => read_password()
+            .and_then(|password| users::add(name, role, &password).map_err(|err| invalid(&err))),
+        ["remove-user", name] => users::remove(name).map_err(|err| invalid(&err)),
+        // The token is only shown once, the tokens file keeps its hash
+        ["mint-token", name, scopes] => tokens::mint(name, scopes)
+            .map(|token| println!("{}", token))
+            .map_err(|err| invalid(&err)),
+        ["revoke-token", id_or_name] => tokens::revoke(id_or_name)
+            .map(|count| eprintln!("Revoked {} token(s)", count))
+            .map_err(|err| invalid(&err)),
+        // Writes the .gz and .br copies that /files sends to clients accepting them
+        ["compress-assets"] => {
+            compression::compress_assets().map(|count| eprintln!("Compressed {} file(s)", count))
         }
-        generate_list_page(&mut state);
+        [] => return None,
+        _ => Err(invalid(
+            "Usage: gondola-rs [hash-password | add-user <name> <role> | remove-user <name> \
+             | mint-token <name> <scope,...> | revoke-token <id or name> | compress-assets]",
+        )),
+    };
+    if result.is_ok() {
+        eprintln!("Done");
     }
+    Some(result)
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    if let Some(result) = subcommand(&std::env::args().skip(1).collect::<Vec<_>>()) {
+        return result;
+    }
     let mut state = State::default();
+    let rescan_start = Instant::now();
     read_state_from_disk(&mut state)?;
-    generate_list_page(&mut state);
+    state.metrics.observe_rescan(rescan_start.elapsed(), true);
+    generate_list_page(&state);
+    state.health.loaded.store(true, Ordering::Relaxed);
+    state.health.last_rescan_ok.store(true, Ordering::Relaxed);
 
+    let (updates, update_receiver) = mpsc::channel();
+    state.updates = updates;
     let updater_state = state.clone();
+    state.health.updater_running.store(true, Ordering::Relaxed);
     thread::Builder::new()
         .name("state-updater".to_string())
         .spawn(move || {
-            update_state(updater_state);
+            let health = updater_state.health.clone();
+            let lgr_important = updater_state
+                .lgr_important
+                .borrow()
+                .clone_add_context("state-updater");
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                update_state(updater_state, update_receiver)
+            }));
+            health.updater_running.store(false, Ordering::Relaxed);
+            let reason = match &result {
+                Err(payload) => payload
+                    .downcast_ref::<&str>()
+                    .map(|x| x.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string()),
+                Ok(()) => "returned".to_string(),
+            };
+            error!(lgr_important, "State updater thread stopped"; "reason" => reason);
         })
         .expect("Unable to start the updater thread");
 
     info!(state.lgr.borrow(), "Initializing"; "working directory" => InDebug(&std::env::current_dir()));
 
+    if let Some(port) = METRICS_PORT {
+        let metrics_state = state.clone();
+        let metrics_server = HttpServer::new(move || {
+            App::new()
+                .app_data(Data::new(metrics_state.clone()))
+                .route("/metrics", web::get().to(show_metrics))
+        })
+        .workers(1)
+        .bind(format!("127.0.0.1:{}", port))?
+        .run();
+        actix_web::rt::spawn(metrics_server);
+    }
+
     HttpServer::new(move || {
         let seed = state.random_counter.fetch_add(1, Ordering::Relaxed);
         let mut thread_state = state.clone();
@@ -925,28 +2767,119 @@ async fn main() -> std::io::Result<()> {
         info!(thread_state.lgr.borrow(), "Starting worker thread"; "random seed" => seed);
 
         let benchmark_log = state.lgr.borrow().clone_with_context("benchmark");
-        let request_log = state.lgr.borrow().clone_with_context("request");
+
+        let access_log = state.access_log.clone();
+        let metrics = state.metrics.clone();
+        let style_count = state.style_count.clone();
 
         App::new()
             .app_data(Data::new(thread_state))
             .wrap_fn(move |req, srv| {
-                let request = format!("{:?}", req);
-                info!(request_log, "Incoming request"; "data" => request);
-                benchmark! {
-                    |duration| info!(benchmark_log, "Total request time"; "duration" => InDebug(&duration)),
-                    srv.call(req)
+                let request_id = RequestId::assign(&req);
+                let mut entry = Entry::from_request(&req, &request_id);
+                let wants_html = req
+                    .headers()
+                    .get(header::ACCEPT)
+                    .and_then(|x| x.to_str().ok())
+                    .map_or(false, |x| x.contains("text/html"));
+                let access_log = access_log.clone();
+                let benchmark_log = benchmark_log.clone();
+                let metrics = metrics.clone();
+                let style_count = style_count.clone();
+                let before = Instant::now();
+                let response = srv.call(req);
+                async move {
+                    let mut response = response.await?;
+                    let duration = before.elapsed();
+                    info!(benchmark_log, "Total request time"; "duration" => InDebug(&duration), "request_id" => request_id; clone request_id);
+                    let status = response.status();
+                    let is_html = response
+                        .headers()
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|x| x.to_str().ok())
+                        .map_or(false, |x| x.starts_with("text/html"));
+                    if wants_html
+                        && !is_html
+                        && (status.is_client_error() || status.is_server_error())
+                    {
+                        response = response.map_body(|head, body: BoxBody| {
+                            let message = body
+                                .try_into_bytes()
+                                .map(|x| String::from_utf8_lossy(&x).into_owned())
+                                .unwrap_or_default();
+                            head.headers_mut()
+                                .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+                            BoxBody::new(error_page(
+                                status,
+                                &message,
+                                &request_id,
+                                style_count.load(Ordering::Relaxed),
+                            ))
+                        });
+                    }
+                    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+                        response
+                            .headers_mut()
+                            .insert(REQUEST_ID_HEADER.clone(), value);
+                    }
+                    entry.status = response.status().as_u16();
+                    entry.duration = duration;
+                    if let BodySize::Sized(size) = response.response().body().size() {
+                        entry.size = Some(size);
+                    }
+                    access_log.log(&entry);
+                    metrics.observe_request(
+                        response
+                            .request()
+                            .match_pattern()
+                            .as_deref()
+                            .unwrap_or("unmatched"),
+                        response.status().as_u16(),
+                        duration,
+                    );
+                    Ok(response)
                 }
             })
             .route("/", web::get().to(index))
             .route("/random", web::get().to(play_random_video))
             .route("/random-raw", web::get().to(play_random_video_raw))
             .route("/next/{previous}", web::get().to(play_next_video))
+            .route("/prev/{name}", web::get().to(play_previous_video))
+            .route("/back/{current}", web::get().to(play_previous_random_video))
+            .route("/history", web::get().to(show_history))
+            .route("/playlist/{id}", web::get().to(show_playlist))
+            .route(
+                "/playlist/{id}/{index}",
+                web::get().to(render_playlist_page),
+            )
+            .route("/api/playlist", web::post().to(create_playlist))
+            .route("/today", web::get().to(play_todays_video))
+            .route("/api/today", web::get().to(api_today))
+            .route("/api/rescan", web::post().to(api_rescan))
+            .service(web::scope("/api/admin").configure(adminapi::configure))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
             .route("/robots.txt", web::get().to(robots))
             .route("/list", web::get().to(list_all_videos))
             .route("favicon.ico", web::get().to(redirect_favicon))
             .route("/files/{filename:.*}", web::get().to(get_file))
+            .route("/login", web::get().to(show_login))
+            .route("/login", web::post().to(login))
+            .route("/logout", web::post().to(logout))
             .route("/shell", web::get().to(shell))
             .route("/shell", web::post().to(do_shell))
+            .route("/logs", web::get().to(show_logs))
+            .route("/upload", web::get().to(upload::show_upload))
+            .route("/upload", web::post().to(upload::upload))
+            .route("/logs", web::post().to(search_logs))
+            .route("/admin", web::get().to(dashboard::show))
+            .route("/audit", web::get().to(show_audit))
+            .route("/audit", web::post().to(search_audit))
+            .configure(|config| {
+                if METRICS_PORT.is_none() {
+                    config.route("/metrics", web::get().to(show_metrics));
+                }
+            })
             .route("/{name}", web::get().to(render_video_page))
             .default_service(web::get().to(unknown_route))
     })
@@ -969,4 +2902,96 @@ mod tests {
             assert_ne!(random1.gen::<usize>(), random2.gen::<usize>());
         }
     }
+
+    #[test]
+    fn gondola_of_the_day_does_not_repeat_within_window() {
+        assert_eq!(gondola_of_the_day(&[], 100, &[]), None);
+
+        let names: Vec<String> = (0..97).map(|x| format!("{}.webm", x)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+        let mut picks = vec![];
+        for day in 0..1000 {
+            let recent = &picks[picks.len().saturating_sub(29)..];
+            let pick = gondola_of_the_day(&names, day, recent).unwrap();
+            picks.push(pick);
+        }
+        for window in picks.windows(30) {
+            for (index, pick) in window.iter().enumerate() {
+                assert!(!window[index + 1..].contains(pick));
+            }
+        }
+
+        let mut uploaded = names.clone();
+        uploaded.push("new.webm");
+        for day in 0..100 {
+            let pick = gondola_of_the_day(&uploaded, day, &[]).unwrap();
+            assert!(pick == "new.webm" || Some(pick) == gondola_of_the_day(&names, day, &[]));
+        }
+        assert_eq!(
+            gondola_of_the_day(&names[..1], 5, &names[..1]),
+            Some(names[0])
+        );
+
+        // Saved picks have to stay valid, so the ranking must not change between builds
+        let names = ["A.webm", "B.webm", "C.webm", "D.webm"];
+        let picks: Vec<_> = (20000..20003)
+            .filter_map(|day| gondola_of_the_day(&names, day, &[]))
+            .collect();
+        assert_eq!(picks, ["B.webm", "D.webm", "A.webm"]);
+    }
+
+    #[test]
+    fn file_paths_are_plain_names() {
+        assert_eq!(
+            file_path("video/A.webm"),
+            Some(PathBuf::from("video/A.webm"))
+        );
+        assert_eq!(
+            file_path("video/./A.webm"),
+            Some(PathBuf::from("video/A.webm"))
+        );
+        assert_eq!(file_path("./video/A.webm"), None);
+        assert_eq!(file_path("video/../password"), None);
+        assert_eq!(file_path("/etc/passwd"), None);
+    }
+
+    #[test]
+    fn private_files_are_refused() {
+        for refused in [
+            "logs/log",
+            "logs/important",
+            "logs/access",
+            "statistics/.today",
+            "statistics/A.webm",
+            "visibility/A.webm",
+            "logs/./log",
+        ] {
+            assert!(is_private(&file_path(refused).unwrap()), "{}", refused);
+        }
+        for served in ["video/A.webm", "css/style.css", "video/logs"] {
+            assert!(!is_private(&file_path(served).unwrap()), "{}", served);
+        }
+    }
+
+    #[test]
+    fn login_only_returns_to_local_paths() {
+        for local in ["/shell", "/upload", "/A.webm?t=3", "/playlist/abc"] {
+            assert_eq!(local_path(local), local);
+        }
+        for foreign in [
+            "",
+            "shell",
+            "//evil.com",
+            "/\\evil.com",
+            "/\t/evil.com",
+            "/\n/evil.com",
+            "/\r\n/evil.com",
+            " //evil.com",
+            "/ /evil.com",
+            "https://evil.com",
+            "javascript:alert(1)",
+        ] {
+            assert_eq!(local_path(foreign), "/shell", "{:?}", foreign);
+        }
+    }
 }
@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+static BUCKETS: [f64; 11] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, limit) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if seconds <= *limit {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Counters and timings exposed in the Prometheus text format on /metrics
+#[derive(Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, u16), Histogram>>,
+    list_render_seconds: AtomicU64,
+    rescan_seconds: AtomicU64,
+    rescan_last: AtomicU64,
+    rescan_last_success: AtomicU64,
+    pub log_write_errors: Arc<AtomicU64>,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, route: &str, status: u16, duration: Duration) {
+        self.requests
+            .lock()
+            .unwrap()
+            .entry((route.to_string(), status))
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_list_render(&self, duration: Duration) {
+        self.list_render_seconds
+            .store(duration.as_secs_f64().to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn observe_rescan(&self, duration: Duration, success: bool) {
+        self.rescan_seconds
+            .store(duration.as_secs_f64().to_bits(), Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.rescan_last.store(now.as_secs(), Ordering::Relaxed);
+        if success {
+            self.rescan_last_success
+                .store(now.as_secs(), Ordering::Relaxed);
+        }
+    }
+
+    /// When the last rescan finished and how long it took, `None` before the first one
+    pub fn last_rescan(&self) -> Option<(SystemTime, Duration)> {
+        let last = self.rescan_last.load(Ordering::Relaxed);
+        let seconds = f64::from_bits(self.rescan_seconds.load(Ordering::Relaxed));
+        (last != 0).then(|| {
+            (
+                SystemTime::UNIX_EPOCH + Duration::from_secs(last),
+                Duration::from_secs_f64(seconds),
+            )
+        })
+    }
+
+    /// Render all metrics, `views` yields the name and view count of each video to show
+    pub fn render<'a>(
+        &self,
+        videos: usize,
+        views: impl Iterator<Item = (&'a str, usize)>,
+    ) -> String {
+        let mut out = String::new();
+
+        let requests = self.requests.lock().unwrap();
+        describe(
+            &mut out,
+            "gondola_requests_total",
+            "counter",
+            "Requests handled, by route and status",
+        );
+        for ((route, status), histogram) in requests.iter() {
+            out += &format!(
+                "gondola_requests_total{{route=\"{}\",status=\"{}\"}} {}\n",
+                escape(route),
+                status,
+                histogram.count
+            );
+        }
+
+        describe(
+            &mut out,
+            "gondola_request_duration_seconds",
+            "histogram",
+            "Time taken to handle requests, by route and status",
+        );
+        for ((route, status), histogram) in requests.iter() {
+            let labels = format!("route=\"{}\",status=\"{}\"", escape(route), status);
+            for (count, limit) in histogram.buckets.iter().zip(BUCKETS.iter()) {
+                out += &format!(
+                    "gondola_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, limit, count
+                );
+            }
+            out += &format!(
+                "gondola_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+                labels, histogram.count
+            );
+            out += &format!(
+                "gondola_request_duration_seconds_sum{{{}}} {}\n",
+                labels, histogram.sum
+            );
+            out += &format!(
+                "gondola_request_duration_seconds_count{{{}}} {}\n",
+                labels, histogram.count
+            );
+        }
+        drop(requests);
+
+        describe(
+            &mut out,
+            "gondola_videos",
+            "gauge",
+            "Number of videos in the index",
+        );
+        out += &format!("gondola_videos {}\n", videos);
+
+        describe(
+            &mut out,
+            "gondola_video_views_total",
+            "counter",
+            "Views of each video",
+        );
+        for (name, views) in views {
+            out += &format!(
+                "gondola_video_views_total{{video=\"{}\"}} {}\n",
+                escape(name),
+                views
+            );
+        }
+
+        describe(
+            &mut out,
+            "gondola_rescan_duration_seconds",
+            "gauge",
+            "Duration of the last rescan of the video directory",
+        );
+        out += &format!(
+            "gondola_rescan_duration_seconds {}\n",
+            f64::from_bits(self.rescan_seconds.load(Ordering::Relaxed))
+        );
+
+        describe(
+            &mut out,
+            "gondola_rescan_last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful rescan",
+        );
+        out += &format!(
+            "gondola_rescan_last_success_timestamp_seconds {}\n",
+            self.rescan_last_success.load(Ordering::Relaxed)
+        );
+
+        describe(
+            &mut out,
+            "gondola_list_render_duration_seconds",
+            "gauge",
+            "Time taken to generate the /list page",
+        );
+        out += &format!(
+            "gondola_list_render_duration_seconds {}\n",
+            f64::from_bits(self.list_render_seconds.load(Ordering::Relaxed))
+        );
+
+        describe(
+            &mut out,
+            "gondola_log_write_errors_total",
+            "counter",
+            "Failed writes to the log files",
+        );
+        out += &format!(
+            "gondola_log_write_errors_total {}\n",
+            self.log_write_errors.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    *out += &format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_metrics() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.last_rescan(), None);
+        metrics.observe_request("/{name}", 200, Duration::from_millis(3));
+        metrics.observe_request("/{name}", 200, Duration::from_millis(30));
+        metrics.observe_rescan(Duration::from_millis(1500), true);
+        let out = metrics.render(2, [("A \"1\".webm", 7)].into_iter());
+
+        assert!(out.contains("# TYPE gondola_requests_total counter\n"));
+        assert!(out.contains("gondola_requests_total{route=\"/{name}\",status=\"200\"} 2\n"));
+        let bucket = |le| {
+            format!("gondola_request_duration_seconds_bucket{{route=\"/{{name}}\",status=\"200\",le=\"{}\"}}", le)
+        };
+        assert!(out.contains(&format!("{} 0\n", bucket("0.001"))));
+        assert!(out.contains(&format!("{} 1\n", bucket("0.005"))));
+        assert!(out.contains(&format!("{} 2\n", bucket("+Inf"))));
+        assert!(out.contains("gondola_videos 2\n"));
+        assert!(out.contains("gondola_video_views_total{video=\"A \\\"1\\\".webm\"} 7\n"));
+        assert!(out.contains("gondola_rescan_duration_seconds 1.5\n"));
+        assert_eq!(
+            metrics.last_rescan().map(|(_, duration)| duration),
+            Some(Duration::from_millis(1500))
+        );
+    }
+}
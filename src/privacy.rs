@@ -0,0 +1,141 @@
+use {
+    crate::{
+        auth,
+        config::{IP_ANONYMIZATION, LOG_QUERY_STRINGS},
+    },
+    actix_web::{http::header, HttpRequest},
+    rand::Rng,
+    sha2::{Digest, Sha512},
+    std::{
+        fmt::Write,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Mutex,
+    },
+};
+
+#[allow(dead_code)] // The mode in use is picked in config.rs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IpAnonymization {
+    /// Log addresses as they are
+    None,
+    /// Zero all but the first 24 bits of IPv4 and the first 48 bits of IPv6 addresses
+    Truncate,
+    /// Replace addresses by a salted hash, the salt changes every time the server starts
+    Hash,
+}
+
+/// Headers that are never logged, either because they carry credentials or client addresses
+static REDACTED_HEADERS: [header::HeaderName; 5] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::FORWARDED,
+    header::HeaderName::from_static("x-forwarded-for"),
+    header::HeaderName::from_static("x-real-ip"),
+];
+
+fn salt() -> [u8; 32] {
+    static SALT: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+    *SALT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| rand::thread_rng().gen())
+}
+
+fn anonymize(address: &str, mode: IpAnonymization) -> String {
+    if mode == IpAnonymization::None {
+        return address.to_string();
+    }
+    let ip = match address.parse::<SocketAddr>() {
+        Ok(socket) => socket.ip(),
+        Err(_) => match address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return "unknown".to_string(),
+        },
+    };
+    match mode {
+        IpAnonymization::None => unreachable!(),
+        IpAnonymization::Truncate => match ip {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                Ipv4Addr::new(a, b, c, 0).to_string()
+            }
+            IpAddr::V6(ip) => {
+                let [a, b, c, ..] = ip.segments();
+                Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0).to_string()
+            }
+        },
+        IpAnonymization::Hash => {
+            let mut hasher = Sha512::new();
+            hasher.update(salt());
+            hasher.update(ip.to_string().as_bytes());
+            hasher.finalize()[..8]
+                .iter()
+                .fold(String::new(), |mut out, byte| {
+                    let _ = write!(out, "{:02x}", byte);
+                    out
+                })
+        }
+    }
+}
+
+/// Anonymize a client address according to `IP_ANONYMIZATION`
+pub fn anonymize_ip(address: &str) -> String {
+    anonymize(address, IP_ANONYMIZATION)
+}
+
+/// Remove the query string unless `LOG_QUERY_STRINGS` is set
+pub fn redact_path(path: &str) -> &str {
+    if LOG_QUERY_STRINGS {
+        path
+    } else {
+        path.split('?').next().unwrap_or_default()
+    }
+}
+
+/// Describe a request for the logs without credentials, cookies or full client addresses
+pub fn redact_request(request: &HttpRequest) -> String {
+    let mut out = format!(
+        "{} {} {:?} from {}",
+        request.method(),
+        redact_path(&request.uri().to_string()),
+        request.version(),
+        anonymize_ip(&auth::client(request)),
+    );
+    for (name, value) in request.headers() {
+        if !REDACTED_HEADERS.contains(name) {
+            let _ = write!(out, "\n{}: {}", name, value.to_str().unwrap_or("<binary>"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymizes_addresses() {
+        assert_eq!(
+            anonymize("203.0.113.77", IpAnonymization::Truncate),
+            "203.0.113.0"
+        );
+        assert_eq!(
+            anonymize("203.0.113.77:51234", IpAnonymization::Truncate),
+            "203.0.113.0"
+        );
+        assert_eq!(
+            anonymize("2001:db8:aa:bb:cc::1", IpAnonymization::Truncate),
+            "2001:db8:aa::"
+        );
+        assert_eq!(
+            anonymize("203.0.113.77", IpAnonymization::None),
+            "203.0.113.77"
+        );
+        assert_eq!(anonymize("garbage", IpAnonymization::Truncate), "unknown");
+
+        let hashed = anonymize("203.0.113.77", IpAnonymization::Hash);
+        assert_eq!(hashed.len(), 16);
+        assert_eq!(hashed, anonymize("203.0.113.77:80", IpAnonymization::Hash));
+        assert_ne!(hashed, anonymize("203.0.113.78", IpAnonymization::Hash));
+    }
+}
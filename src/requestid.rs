@@ -0,0 +1,71 @@
+use {
+    actix_web::{
+        dev::{Payload, ServiceRequest},
+        http::header::HeaderName,
+        FromRequest, HttpMessage, HttpRequest,
+    },
+    rand::Rng,
+    std::{
+        fmt,
+        future::{ready, Ready},
+    },
+};
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Identifies a request across the site, important, benchmark and access logs
+#[derive(Clone, Debug)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Take the ID given by the proxy in front of the site if it looks sane, otherwise make one
+    pub fn assign(request: &ServiceRequest) -> Self {
+        let id = request
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|x| x.to_str().ok())
+            .filter(|x| valid(x))
+            .map(String::from)
+            .unwrap_or_else(|| format!("{:016x}", rand::thread_rng().gen::<u64>()));
+        let id = Self(id);
+        request.extensions_mut().insert(id.clone());
+        id
+    }
+
+    /// The ID assigned to a request by the middleware, for code that already has the request
+    pub fn of(request: &HttpRequest) -> Self {
+        request
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(|| RequestId("-".to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Self::of(request)))
+    }
+}
+
+/// Incoming IDs end up in log files, so only accept short ones without spaces or quotes
+fn valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 128
+        && id
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '-' | '_' | '.' | ':'))
+}
@@ -0,0 +1,139 @@
+use {
+    crate::{
+        config::SESSION_LIFETIME,
+        requestid::RequestId,
+        users::{Actor, User},
+    },
+    hmac::{Hmac, Mac},
+    rand::Rng,
+    sha2::Sha512,
+    std::{collections::HashMap, fmt::Write, sync::Mutex, time::Instant},
+    subtle::ConstantTimeEq,
+};
+
+pub static SESSION_COOKIE: &str = "session";
+
+/// A logged in admin
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub user: User,
+    /// Sent along with every admin form, a cross-site form carries the cookie but not this token
+    pub csrf: String,
+    expires: Instant,
+}
+
+impl Session {
+    pub fn csrf_matches(&self, token: &str) -> bool {
+        self.csrf.as_bytes().ct_eq(token.as_bytes()).into()
+    }
+
+    pub fn actor(&self, request_id: &RequestId) -> Actor {
+        Actor {
+            user: self.user.name.clone(),
+            request_id: request_id.clone(),
+        }
+    }
+}
+
+/// Sessions are kept in memory, so restarting the server logs everyone out. The cookie holds the
+/// session ID signed with a key made at startup, unsigned IDs are rejected without a lookup.
+pub struct Sessions {
+    key: [u8; 32],
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            key: rand::thread_rng().gen(),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+fn random_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+impl Sessions {
+    fn sign(&self, id: &str) -> String {
+        let mut mac =
+            Hmac::<Sha512>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(id.as_bytes());
+        mac.finalize()
+            .into_bytes()
+            .iter()
+            .fold(String::new(), |mut out, byte| {
+                let _ = write!(out, "{:02x}", byte);
+                out
+            })
+    }
+
+    /// The session ID in a cookie value of the form `{id}.{signature}`
+    fn id<'a>(&self, cookie: &'a str) -> Option<&'a str> {
+        let (id, signature) = cookie.split_once('.')?;
+        bool::from(self.sign(id).as_bytes().ct_eq(signature.as_bytes())).then_some(id)
+    }
+
+    /// Start a session, returns the cookie value
+    pub fn create(&self, user: User) -> String {
+        let id = random_token();
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(
+            id.clone(),
+            Session {
+                user,
+                csrf: random_token(),
+                expires: now + SESSION_LIFETIME,
+            },
+        );
+        format!("{}.{}", id, self.sign(&id))
+    }
+
+    pub fn get(&self, cookie: &str) -> Option<Session> {
+        let id = self.id(cookie)?;
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some(session) if session.expires > Instant::now() => Some(session.clone()),
+            Some(_) => {
+                sessions.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn remove(&self, cookie: &str) {
+        if let Some(id) = self.id(cookie) {
+            self.sessions.lock().unwrap().remove(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::users::Role};
+
+    #[test]
+    fn signs_cookies() {
+        let sessions = Sessions::default();
+        let cookie = sessions.create(User {
+            name: "alice".to_string(),
+            role: Role::Editor,
+        });
+        let session = sessions.get(&cookie).unwrap();
+        assert!(session.csrf_matches(&session.csrf.clone()));
+        assert!(!session.csrf_matches(""));
+
+        let (id, _) = cookie.split_once('.').unwrap();
+        assert!(sessions.get(id).is_none());
+        assert!(sessions.get(&format!("{}.00", id)).is_none());
+        assert!(Sessions::default().get(&cookie).is_none());
+        assert_eq!(session.user.role, Role::Editor);
+
+        sessions.remove(&cookie);
+        assert!(sessions.get(&cookie).is_none());
+    }
+}
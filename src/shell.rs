@@ -0,0 +1,621 @@
+use {
+    crate::{
+        announcements, audit,
+        config::SHELL_HISTORY_LENGTH,
+        delete_playlist, logs, request_rescan,
+        requestid::RequestId,
+        save_playlist, set_source, set_visibility,
+        users::{self, Actor, Role, User},
+        State, Visibility,
+    },
+    actix_web::HttpRequest,
+    chrono::{DateTime, Local},
+    std::sync::atomic::Ordering,
+};
+
+/// How the words after a command name are turned into arguments
+pub enum Kind {
+    /// A single word, quote it to include spaces
+    Word,
+    /// A log level name or number
+    Level,
+    /// All remaining words, at least one
+    Words,
+    /// The rest of the line as typed, quotes included
+    Text,
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub optional: bool,
+}
+
+pub enum Value {
+    Word(String),
+    Level(u8),
+    Words(Vec<String>),
+    Text(String),
+}
+
+/// Arguments in the order the command declares them, `None` for missing optional arguments
+pub struct Args(Vec<Option<Value>>);
+
+impl Args {
+    fn word(&self, index: usize) -> Option<&str> {
+        match self.0.get(index) {
+            Some(Some(Value::Word(word))) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn level(&self, index: usize) -> Option<u8> {
+        match self.0.get(index) {
+            Some(Some(Value::Level(level))) => Some(*level),
+            _ => None,
+        }
+    }
+
+    fn words(&self, index: usize) -> Vec<String> {
+        match self.0.get(index) {
+            Some(Some(Value::Words(words))) => words.clone(),
+            _ => vec![],
+        }
+    }
+
+    fn text(&self, index: usize) -> &str {
+        match self.0.get(index) {
+            Some(Some(Value::Text(text))) => text,
+            _ => "",
+        }
+    }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    /// Least role allowed to run the command
+    pub role: Role,
+    pub run: fn(&State, &Args, &Actor) -> Result<String, String>,
+}
+
+impl Command {
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in self.args {
+            let name = match arg.kind {
+                Kind::Words => format!("<{}>...", arg.name),
+                _ => format!("<{}>", arg.name),
+            };
+            if arg.optional {
+                usage += &format!(" [{}]", name);
+            } else {
+                usage += &format!(" {}", name);
+            }
+        }
+        usage
+    }
+
+    fn parse(&self, mut rest: &str) -> Result<Args, String> {
+        let mut values = vec![];
+        for arg in self.args {
+            let value = match arg.kind {
+                Kind::Text => {
+                    let text = rest.trim();
+                    rest = "";
+                    (!text.is_empty()).then(|| Value::Text(text.to_string()))
+                }
+                Kind::Words => {
+                    let mut words = vec![];
+                    while let Some((word, remaining)) = next_word(rest)? {
+                        words.push(word);
+                        rest = remaining;
+                    }
+                    (!words.is_empty()).then_some(Value::Words(words))
+                }
+                Kind::Word | Kind::Level => match next_word(rest)? {
+                    Some((word, remaining)) => {
+                        rest = remaining;
+                        Some(match arg.kind {
+                            Kind::Level => Value::Level(
+                                logs::parse_level(&word)
+                                    .ok_or_else(|| format!("Unknown level: {}", word))?,
+                            ),
+                            _ => Value::Word(word),
+                        })
+                    }
+                    None => None,
+                },
+            };
+            if value.is_none() && !arg.optional {
+                return Err(format!("Missing <{}>, usage: {}", arg.name, self.usage()));
+            }
+            values.push(value);
+        }
+        if next_word(rest)?.is_some() {
+            return Err(format!("Too many arguments, usage: {}", self.usage()));
+        }
+        Ok(Args(values))
+    }
+}
+
+/// Split off the next word, honoring "double quotes", 'single quotes' and backslash escapes
+fn next_word(input: &str) -> Result<Option<(String, &str)>, String> {
+    let input = input.trim_start();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((index, x)) = chars.next() {
+        match (quote, x) {
+            (None, x) if x.is_whitespace() => return Ok(Some((word, &input[index..]))),
+            (None, '"' | '\'') => quote = Some(x),
+            (Some(open), x) if x == open => quote = None,
+            (None | Some('"'), '\\') => match chars.next() {
+                Some((_, escaped)) => word.push(escaped),
+                None => return Err("Nothing to escape at the end of the line".to_string()),
+            },
+            (_, x) => word.push(x),
+        }
+    }
+    match quote {
+        Some(open) => Err(format!("Missing closing {}", open)),
+        None => Ok(Some((word, ""))),
+    }
+}
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "announce",
+        args: &[Arg {
+            name: "[severity=info|warning|critical] [on=all|video|list] [from=<time>] [until=<time>] markdown",
+            kind: Kind::Text,
+            optional: false,
+        }],
+        help: "Adds an announcement at the top of the pages, written in Markdown (HTML is sanitized). It is shown on every page, or only on the video pages or /list, between the optional times, written as YYYY-MM-DDTHH:MM in UTC",
+        role: Role::Announcer,
+        run: |state, args, actor| {
+            announcements::add(state, announcements::parse_options(args.text(0))?, actor)
+        },
+    },
+    Command {
+        name: "denounce",
+        args: &[Arg {
+            name: "id|all",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Removes an announcement, or all of them",
+        role: Role::Announcer,
+        run: |state, args, actor| match args.word(0).unwrap_or_default() {
+            "all" => announcements::remove(state, None, actor),
+            id => match id.parse() {
+                Ok(id) => announcements::remove(state, Some(id), actor),
+                Err(_) => Err(format!("Not an announcement id: {}", id)),
+            },
+        },
+    },
+    Command {
+        name: "announcements",
+        args: &[],
+        help: "Lists the announcements with their id, severity and schedule",
+        role: Role::Announcer,
+        run: |state, _, _| Ok(announcements::list(state)),
+    },
+    Command {
+        name: "style",
+        args: &[],
+        help: "Increments the style counter so style updates are shown to users",
+        role: Role::Editor,
+        run: |state, _, _| {
+            state.style_count.fetch_add(1, Ordering::Relaxed);
+            Ok("Style count increment".to_string())
+        },
+    },
+    Command {
+        name: "playlist",
+        args: &[
+            Arg {
+                name: "id",
+                kind: Kind::Word,
+                optional: false,
+            },
+            Arg {
+                name: "video",
+                kind: Kind::Words,
+                optional: false,
+            },
+        ],
+        help: "Creates or replaces the playlist shown at /playlist/<id>",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            save_playlist(
+                state,
+                args.word(0).unwrap_or_default(),
+                args.words(1),
+                actor,
+            )
+            .map(|url| format!("Playlist saved: {}", url))
+        },
+    },
+    Command {
+        name: "unplaylist",
+        args: &[Arg {
+            name: "id",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Removes a playlist",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            delete_playlist(state, args.word(0).unwrap_or_default(), actor)
+                .map(|()| "Playlist removed".to_string())
+        },
+    },
+    Command {
+        name: "source",
+        args: &[
+            Arg {
+                name: "video",
+                kind: Kind::Word,
+                optional: false,
+            },
+            Arg {
+                name: "source",
+                kind: Kind::Text,
+                optional: false,
+            },
+        ],
+        help: "Sets the source shown below a video and on the list page",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            set_source(
+                state,
+                args.word(0).unwrap_or_default(),
+                Some(args.text(1).to_string()),
+                actor,
+            )
+        },
+    },
+    Command {
+        name: "unsource",
+        args: &[Arg {
+            name: "video",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Removes the source of a video",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            set_source(state, args.word(0).unwrap_or_default(), None, actor)
+        },
+    },
+    Command {
+        name: "publish",
+        args: &[Arg {
+            name: "video",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Lists a video again and plays it at random and in order",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            set_visibility(
+                state,
+                args.word(0).unwrap_or_default(),
+                Visibility::Public,
+                actor,
+            )
+        },
+    },
+    Command {
+        name: "unlist",
+        args: &[Arg {
+            name: "video",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Removes a video from /list, random and ordered play, it stays reachable through its URL",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            set_visibility(
+                state,
+                args.word(0).unwrap_or_default(),
+                Visibility::Unlisted,
+                actor,
+            )
+        },
+    },
+    Command {
+        name: "hide",
+        args: &[Arg {
+            name: "video",
+            kind: Kind::Word,
+            optional: false,
+        }],
+        help: "Stops serving a video, its file and view count are kept",
+        role: Role::Editor,
+        run: |state, args, actor| {
+            set_visibility(
+                state,
+                args.word(0).unwrap_or_default(),
+                Visibility::Hidden,
+                actor,
+            )
+        },
+    },
+    Command {
+        name: "rescan",
+        args: &[],
+        help: "Picks up new, changed and removed videos and sources right away instead of waiting for the next scan",
+        role: Role::Editor,
+        run: |state, _, _| request_rescan(&state.updates).map(|x| x.summary()),
+    },
+    Command {
+        name: "loglevel",
+        args: &[
+            Arg {
+                name: "logger[:context]",
+                kind: Kind::Word,
+                optional: true,
+            },
+            Arg {
+                name: "level",
+                kind: Kind::Level,
+                optional: true,
+            },
+        ],
+        help: "Shows or changes how verbose the site or important logger (or one of its contexts) is, the level is trace, debug, info, warn, error or 0-255",
+        role: Role::Admin,
+        run: change_log_level,
+    },
+    Command {
+        name: "help",
+        args: &[Arg {
+            name: "command",
+            kind: Kind::Word,
+            optional: true,
+        }],
+        help: "Shows the usage of a command, or of all commands the user may run",
+        role: Role::Announcer,
+        run: |_, args, actor| match args.word(0) {
+            Some(name) => find(name).map(|x| format!("{} - {}", x.usage(), x.help)),
+            None => {
+                let role = users::role(&actor.user);
+                Ok(COMMANDS
+                    .iter()
+                    .filter(|x| role.map_or(false, |role| x.role <= role))
+                    .map(|x| format!("{} - {}", x.usage(), x.help))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+        },
+    },
+];
+
+/// The level is shared by all clones of the logger
+fn change_log_level(state: &State, args: &Args, _: &Actor) -> Result<String, String> {
+    let (Some(target), Some(level)) = (args.word(0), args.level(1)) else {
+        if args.word(0).is_some() {
+            return Err("Missing <level>".to_string());
+        }
+        return Ok(format!(
+            "site: {}, important: {}",
+            state.lgr.borrow().get_log_level(),
+            state.lgr_important.borrow().get_log_level()
+        ));
+    };
+    let (logger, context) = match target.split_once(':') {
+        Some((logger, context)) => (logger, Some(context)),
+        None => (target, None),
+    };
+    let lgr = match logger {
+        "site" => state.lgr.borrow(),
+        "important" => state.lgr_important.borrow(),
+        _ => return Err(format!("Unknown logger: {}", logger)),
+    };
+    match context {
+        None => {
+            lgr.set_log_level(level);
+            Ok(format!("Log level of {} set to {}", logger, level))
+        }
+        Some(context) if lgr.set_context_specific_log_level(context, level) => Ok(format!(
+            "Log level of {}:{} set to {}",
+            logger, context, level
+        )),
+        Some(context) => Err(format!("Unknown context: {}", context)),
+    }
+}
+
+fn find(name: &str) -> Result<&'static Command, String> {
+    if let Some(command) = COMMANDS.iter().find(|x| x.name == name) {
+        return Ok(command);
+    }
+    let closest = COMMANDS
+        .iter()
+        .map(|x| (distance(name, x.name), x.name))
+        .min()
+        .filter(|(distance, _)| *distance <= 2);
+    Err(match closest {
+        Some((_, suggestion)) => format!(
+            "Unknown command: {} (did you mean {}?), see help",
+            name, suggestion
+        ),
+        None => format!("Unknown command: {}, see help", name),
+    })
+}
+
+/// Levenshtein distance, for suggesting commands
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != *y);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Run a command line such as `playlist best "A.webm" B.webm` on behalf of `user`
+pub fn run(
+    state: &State,
+    line: &str,
+    user: &User,
+    request_id: &RequestId,
+) -> Result<String, String> {
+    let Some((name, rest)) = next_word(line)? else {
+        return Err("No command given, see help".to_string());
+    };
+    let command = find(&name)?;
+    if user.role < command.role {
+        return Err(format!(
+            "{} needs the {} role, {} is {}",
+            command.name, command.role, user.name, user.role
+        ));
+    }
+    let args = command.parse(rest)?;
+    let actor = Actor {
+        user: user.name.clone(),
+        request_id: request_id.clone(),
+    };
+    (command.run)(state, &args, &actor)
+}
+
+pub struct HistoryEntry {
+    pub time: DateTime<Local>,
+    pub user: String,
+    /// Role needed to run the command, unknown commands need the admin role
+    pub role: Role,
+    pub line: String,
+    pub result: Result<String, String>,
+}
+
+impl HistoryEntry {
+    /// Users see their own commands and the commands their role may run
+    pub fn is_visible_to(&self, user: &User) -> bool {
+        self.user == user.name || self.role <= user.role
+    }
+}
+
+/// Role needed to run a command line
+fn required_role(line: &str) -> Role {
+    next_word(line)
+        .ok()
+        .flatten()
+        .and_then(|(name, _)| find(&name).ok())
+        .map_or(Role::Admin, |command| command.role)
+}
+
+/// Remember a command that was run, the oldest entry is dropped past `SHELL_HISTORY_LENGTH`
+pub fn record(state: &State, line: &str, user: &User, result: &Result<String, String>) {
+    let mut history = state.shell_history.write().unwrap();
+    if history.len() >= SHELL_HISTORY_LENGTH {
+        history.pop_front();
+    }
+    history.push_back(HistoryEntry {
+        time: Local::now(),
+        user: user.name.clone(),
+        role: required_role(line),
+        line: line.to_string(),
+        result: result.clone(),
+    });
+}
+
+/// Add a command to the audit log, commands above the user's role are recorded as denied
+pub fn audit(
+    state: &State,
+    request: &HttpRequest,
+    line: &str,
+    user: &User,
+    result: &Result<String, String>,
+) {
+    let line = line.trim();
+    let (name, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let entry = audit::Entry::new(request, &user.name, name, arguments.trim());
+    let entry = match (find(name), result) {
+        (Ok(command), Err(err)) if user.role < command.role => entry.denied(err),
+        _ => entry.result(result),
+    };
+    audit::record(state, entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_lines() {
+        let words = |mut line| {
+            let mut words = vec![];
+            while let Some((word, rest)) = next_word(line).unwrap() {
+                words.push(word);
+                line = rest;
+            }
+            words
+        };
+        assert_eq!(
+            words(r#"playlist  "my list" 'A "1".webm' B\ C.webm"#),
+            ["playlist", "my list", "A \"1\".webm", "B C.webm"]
+        );
+        assert!(next_word("\"unterminated").is_err());
+
+        let playlist = find("playlist").unwrap();
+        assert!(playlist.parse("best").is_err());
+        let args = playlist.parse("best A.webm B.webm").unwrap();
+        assert_eq!(args.word(0), Some("best"));
+        assert_eq!(args.words(1), ["A.webm", "B.webm"]);
+
+        let announce = find("announce").unwrap();
+        let args = announce.parse(r#" <a href="/x">new</a> "#).unwrap();
+        assert_eq!(args.text(0), r#"<a href="/x">new</a>"#);
+
+        assert!(find("loglevel").unwrap().parse("site loud").is_err());
+        assert!(find("style").unwrap().parse("extra").is_err());
+        assert_eq!(
+            find("anounce").err().unwrap(),
+            "Unknown command: anounce (did you mean announce?), see help"
+        );
+    }
+
+    #[test]
+    fn history_is_limited_to_the_role() {
+        let user = |name: &str, role| User {
+            name: name.to_string(),
+            role,
+        };
+        let entry = |name: &str, line: &str| HistoryEntry {
+            time: Local::now(),
+            user: name.to_string(),
+            role: required_role(line),
+            line: line.to_string(),
+            result: Ok(String::new()),
+        };
+        let (announcer, editor, admin) = (
+            user("a", Role::Announcer),
+            user("e", Role::Editor),
+            user("root", Role::Admin),
+        );
+
+        let announcement = entry("e", "announce hello");
+        assert!(announcement.is_visible_to(&announcer));
+        let source = entry("root", "source A.webm https://example.com/secret");
+        assert!(!source.is_visible_to(&announcer));
+        assert!(source.is_visible_to(&editor));
+        let level = entry("root", "loglevel lgr 255");
+        assert!(!level.is_visible_to(&editor));
+        assert!(level.is_visible_to(&admin));
+
+        // Unknown and denied commands stay visible to whoever typed them
+        let typo = entry("a", "logleve lgr 255");
+        assert!(typo.is_visible_to(&announcer));
+        assert!(!typo.is_visible_to(&editor));
+        assert!(entry("a", "loglevel lgr 255").is_visible_to(&announcer));
+    }
+}
@@ -0,0 +1,203 @@
+use {
+    crate::write_atomically,
+    rand::Rng,
+    sha2::{Digest, Sha512},
+    std::{fmt::Write, fs, io, path::Path},
+    subtle::ConstantTimeEq,
+};
+
+/// One API token per line, `<id> <name> <scope,scope,...> <sha512 of the secret>`, written by
+/// `gondola-rs mint-token`
+pub static TOKENS_FILE: &str = "tokens";
+
+/// What a token may change through /api/admin
+pub static SCOPES: [&str; 5] = ["announcement", "source", "visibility", "playlist", "rescan"];
+
+/// A token that was presented and found in the tokens file
+#[derive(Debug, PartialEq)]
+pub struct Token {
+    pub id: String,
+    /// Shown in the logs as the user that made a change
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+impl Token {
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|x| x == scope)
+    }
+}
+
+struct Entry {
+    token: Token,
+    hash: String,
+}
+
+fn hash(secret: &str) -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(secret.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut out, byte| {
+            let _ = write!(out, "{:02x}", byte);
+            out
+        })
+}
+
+fn parse(contents: &str) -> Result<Vec<Entry>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let mut fields = line.split_whitespace();
+            match (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+            ) {
+                (Some(id), Some(name), Some(scopes), Some(hash), None) => Ok(Entry {
+                    token: Token {
+                        id: id.to_string(),
+                        name: name.to_string(),
+                        scopes: scopes.split(',').map(String::from).collect(),
+                    },
+                    hash: hash.to_string(),
+                }),
+                _ => Err(format!(
+                    "Line {} of the tokens file is not `<id> <name> <scopes> <hash>`",
+                    number + 1
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Read on every request, so minting and revoking apply without a restart
+fn read(path: &Path) -> Result<Vec<Entry>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse(&contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(format!("Unable to read the tokens file: {}", err)),
+    }
+}
+
+/// Find the token for a bearer value of the form `gt_{id}_{secret}`, `Ok(None)` when it is not
+/// a valid token
+pub fn verify(bearer: &str) -> Result<Option<Token>, String> {
+    verify_in(Path::new(TOKENS_FILE), bearer)
+}
+
+fn verify_in(path: &Path, bearer: &str) -> Result<Option<Token>, String> {
+    let Some((id, secret)) = bearer.strip_prefix("gt_").and_then(|x| x.split_once('_')) else {
+        return Ok(None);
+    };
+    let hash = hash(secret);
+    Ok(read(path)?
+        .into_iter()
+        .find(|x| x.token.id == id && bool::from(x.hash.as_bytes().ct_eq(hash.as_bytes())))
+        .map(|x| x.token))
+}
+
+fn write(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let contents: String = entries
+        .iter()
+        .map(|x| {
+            format!(
+                "{} {} {} {}\n",
+                x.token.id,
+                x.token.name,
+                x.token.scopes.join(","),
+                x.hash
+            )
+        })
+        .collect();
+    write_atomically(path, &contents)
+}
+
+/// `gondola-rs mint-token <name> <scope,scope,...>`, returns the token, which is not stored
+pub fn mint(name: &str, scopes: &str) -> Result<String, String> {
+    mint_in(Path::new(TOKENS_FILE), name, scopes)
+}
+
+fn mint_in(path: &Path, name: &str, scopes: &str) -> Result<String, String> {
+    if name.is_empty() || name.chars().any(|x| x.is_whitespace() || x.is_control()) {
+        return Err("Token names can not be empty or contain spaces".to_string());
+    }
+    let scopes: Vec<String> = scopes.split(',').map(String::from).collect();
+    if let Some(unknown) = scopes.iter().find(|x| !SCOPES.contains(&x.as_str())) {
+        return Err(format!(
+            "Unknown scope: {}, use {}",
+            unknown,
+            SCOPES.join(", ")
+        ));
+    }
+    let mut entries = read(path)?;
+    let id = format!("{:08x}", rand::thread_rng().gen::<u32>());
+    let secret = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    entries.push(Entry {
+        token: Token {
+            id: id.clone(),
+            name: name.to_string(),
+            scopes,
+        },
+        hash: hash(&secret),
+    });
+    write(path, &entries).map_err(|err| format!("Unable to write the tokens file: {}", err))?;
+    Ok(format!("gt_{}_{}", id, secret))
+}
+
+/// `gondola-rs revoke-token <id or name>`, revokes every token with that ID or name
+pub fn revoke(id_or_name: &str) -> Result<usize, String> {
+    revoke_in(Path::new(TOKENS_FILE), id_or_name)
+}
+
+fn revoke_in(path: &Path, id_or_name: &str) -> Result<usize, String> {
+    let mut entries = read(path)?;
+    let count = entries.len();
+    entries.retain(|x| x.token.id != id_or_name && x.token.name != id_or_name);
+    if entries.len() == count {
+        return Err(format!("No such token: {}", id_or_name));
+    }
+    write(path, &entries).map_err(|err| format!("Unable to write the tokens file: {}", err))?;
+    Ok(count - entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tokens() {
+        let line = format!("0badc0de cron announcement,rescan {}\n", hash("secret"));
+        let entries = parse(&line).unwrap();
+        assert_eq!(entries[0].token.name, "cron");
+        assert!(entries[0].token.allows("rescan"));
+        assert!(!entries[0].token.allows("source"));
+        assert_eq!(entries[0].hash, hash("secret"));
+        assert!(parse("0badc0de cron announcement").is_err());
+    }
+
+    #[test]
+    fn mints_verifies_and_revokes() {
+        let path = std::env::temp_dir().join(format!(
+            "gondola-tokens-{:016x}",
+            rand::thread_rng().gen::<u64>()
+        ));
+        assert!(mint_in(&path, "cron", "rescan,fly").is_err());
+        let bearer = mint_in(&path, "cron", "rescan").unwrap();
+        let token = verify_in(&path, &bearer).unwrap().unwrap();
+        assert_eq!(token.name, "cron");
+        assert!(token.allows("rescan") && !token.allows("source"));
+        assert_eq!(verify_in(&path, &(bearer.clone() + "0")), Ok(None));
+        assert_eq!(verify_in(&path, "secret"), Ok(None));
+
+        assert_eq!(revoke_in(&path, "cron"), Ok(1));
+        assert_eq!(verify_in(&path, &bearer), Ok(None));
+        assert!(revoke_in(&path, "cron").is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,362 @@
+use {
+    crate::{
+        announcements::{self, Target},
+        audit, authorize,
+        config::*,
+        header, logout_form, page_session, refresh_list_page,
+        requestid::RequestId,
+        session::Session,
+        users::{Actor, Role},
+        write_atomically, State, VideoInfo, Visibility,
+    },
+    actix_multipart::{Field, Multipart},
+    actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, Responder},
+    fast_logger::{error, info},
+    futures_util::StreamExt,
+    maud::{html, DOCTYPE},
+    rand::Rng,
+    std::{
+        fs::{self, File},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        sync::atomic::Ordering,
+        time::SystemTime,
+    },
+};
+
+/// Top level routes, a video with one of these names would be unreachable
+static RESERVED_NAMES: [&str; 24] = [
+    "admin",
+    "api",
+    "audit",
+    "back",
+    "favicon.ico",
+    "files",
+    "healthz",
+    "history",
+    "list",
+    "login",
+    "logout",
+    "logs",
+    "metrics",
+    "next",
+    "playlist",
+    "prev",
+    "random",
+    "random-raw",
+    "readyz",
+    "robots.txt",
+    "shell",
+    "today",
+    "upload",
+    "",
+];
+
+/// Longest CSRF token, name or source accepted in the form
+static TEXT_FIELD_LIMIT: usize = 64 * 1024;
+
+pub async fn show_upload(state: web::Data<State>, request: HttpRequest) -> impl Responder {
+    match page_session(&state, &request, "/upload", Role::Admin) {
+        Ok(session) => render(&state, &session, StatusCode::OK, None),
+        Err(response) => response,
+    }
+}
+
+/// Handles the upload form, the CSRF token has to come before the file so unauthorized uploads are
+/// never written to disk
+pub async fn upload(
+    state: web::Data<State>,
+    mut payload: Multipart,
+    request: HttpRequest,
+    request_id: RequestId,
+) -> impl Responder {
+    let session = match page_session(&state, &request, "/upload", Role::Admin) {
+        Ok(session) => session,
+        Err(response) => return response,
+    };
+    let actor = session.actor(&request_id);
+    let result = receive(&state, &mut payload, &request, &actor).await;
+    let entry = audit::Entry::new(&request, &actor.user, &audit::route(&request), "");
+    match &result {
+        Ok(name) => audit::record(&state, entry.result(&Ok(format!("Uploaded /{}", name)))),
+        // Recorded by authorize
+        Err((StatusCode::FORBIDDEN, _)) => {}
+        Err((_, message)) => audit::record(&state, entry.result(&Err(message.clone()))),
+    }
+    match result {
+        Ok(name) => render(
+            &state,
+            &session,
+            StatusCode::CREATED,
+            Some(format!("Uploaded /{}", name)),
+        ),
+        Err((status, message)) => render(&state, &session, status, Some(message)),
+    }
+}
+
+type Rejection = (StatusCode, String);
+
+async fn receive(
+    state: &State,
+    payload: &mut Multipart,
+    request: &HttpRequest,
+    actor: &Actor,
+) -> Result<String, Rejection> {
+    let mut authorized = false;
+    let mut name = String::new();
+    let mut source = String::new();
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        match field.name().unwrap_or_default() {
+            "csrf" => {
+                let csrf = text(&mut field).await?;
+                authorize(state, request, &csrf, Role::Admin).map_err(|err| err.refusal())?;
+                authorized = true;
+            }
+            "name" => name = text(&mut field).await?.trim().to_string(),
+            "source" => source = text(&mut field).await?.trim().to_string(),
+            "file" => {
+                if !authorized {
+                    return Err((
+                        StatusCode::UNAUTHORIZED,
+                        "The CSRF token has to be sent before the file".to_string(),
+                    ));
+                }
+                if name.is_empty() {
+                    name = field
+                        .content_disposition()
+                        .and_then(|x| x.get_filename())
+                        .unwrap_or_default()
+                        .to_string();
+                }
+                check_name(state, &name)?;
+
+                let temporary: PathBuf = [
+                    "files",
+                    "video",
+                    &format!(".upload-{:016x}", rand::thread_rng().gen::<u64>()),
+                ]
+                .iter()
+                .collect();
+                let result = store(state, &mut field, &temporary, &name, &source, actor).await;
+                let _ = blocking(move || fs::remove_file(temporary)).await;
+                return result.map(|()| name);
+            }
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Unknown field in the form".to_string(),
+                ))
+            }
+        }
+    }
+    Err((StatusCode::BAD_REQUEST, "No file in the form".to_string()))
+}
+
+async fn text(field: &mut Field) -> Result<String, Rejection> {
+    match field.bytes(TEXT_FIELD_LIMIT).await {
+        Ok(Ok(bytes)) => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+        Ok(Err(err)) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+        Err(_) => Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Form field is too long".to_string(),
+        )),
+    }
+}
+
+fn check_name(state: &State, name: &str) -> Result<(), Rejection> {
+    let valid = !name.starts_with('.')
+        && name.len() <= 200
+        && !name
+            .chars()
+            .any(|x| x.is_control() || matches!(x, '/' | '\\' | '?' | '#' | '%'));
+    if !valid || RESERVED_NAMES.contains(&name) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Not a valid video name: {}", name),
+        ));
+    }
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    if !UPLOAD_EXTENSIONS.contains(&extension) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!(
+                "Only {} files can be uploaded",
+                UPLOAD_EXTENSIONS.join(", ")
+            ),
+        ));
+    }
+    let path: PathBuf = ["files", "video", name].iter().collect();
+    if state.video_info.read().unwrap().contains_key(name) || path.exists() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("A video named {} already exists", name),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the start of the file looks like a WebM (EBML) or MP4 (ftyp box) video
+fn is_video(start: &[u8]) -> bool {
+    start.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) || start.get(4..8) == Some(b"ftyp")
+}
+
+async fn store(
+    state: &State,
+    field: &mut Field,
+    temporary: &Path,
+    name: &str,
+    source: &str,
+    actor: &Actor,
+) -> Result<(), Rejection> {
+    let internal = |err: io::Error| {
+        let message = format!("Unable to store the upload: {}", err);
+        let (user, request_id) = (actor.user.clone(), actor.request_id.clone());
+        error!(state.lgr_important.borrow(), "Unable to store upload"; "error" => err, "user" => user, "request_id" => request_id);
+        (StatusCode::INTERNAL_SERVER_ERROR, message)
+    };
+
+    let created = temporary.to_path_buf();
+    let mut file = blocking(move || File::create(created))
+        .await
+        .map_err(internal)?;
+    let mut size = 0;
+    let mut start = Vec::with_capacity(12);
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        size += chunk.len();
+        if size > UPLOAD_MAX_BYTES {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Videos can be at most {} bytes", UPLOAD_MAX_BYTES),
+            ));
+        }
+        if start.len() < 12 {
+            let missing = (12 - start.len()).min(chunk.len());
+            start.extend_from_slice(&chunk[..missing]);
+        }
+        file = blocking(move || file.write_all(&chunk).map(|()| file))
+            .await
+            .map_err(internal)?;
+    }
+    if !is_video(&start) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "The file is not a WebM or MP4 video".to_string(),
+        ));
+    }
+    blocking(move || file.sync_all()).await.map_err(internal)?;
+
+    // Linking fails instead of replacing a video that appeared in the meantime
+    let path: PathBuf = ["files", "video", name].iter().collect();
+    let (linked, video) = (temporary.to_path_buf(), path.clone());
+    blocking(move || fs::hard_link(linked, video))
+        .await
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => (
+                StatusCode::CONFLICT,
+                format!("A video named {} already exists", name),
+            ),
+            _ => internal(err),
+        })?;
+    // Written after linking so a refused upload leaves the source of the existing video alone.
+    // The modification time is the one a rescan reads, so the video is not reported as changed.
+    let source_path: PathBuf = ["files", "sources", name].iter().collect();
+    let (video, source_text) = (path.clone(), source.to_string());
+    let added = blocking(move || {
+        if !source_text.is_empty() {
+            if let Err(err) = write_atomically(&source_path, &source_text) {
+                let _ = fs::remove_file(&video);
+                return Err(err);
+            }
+        }
+        Ok(fs::metadata(&video)
+            .and_then(|x| x.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH))
+    })
+    .await
+    .map_err(internal)?;
+
+    {
+        let mut video_infos = state.video_info.write().unwrap();
+        video_infos.insert(
+            name.to_string(),
+            VideoInfo {
+                added,
+                source: (!source.is_empty()).then(|| source.to_string()),
+                views: 0,
+                visibility: Visibility::Public,
+            },
+        );
+        video_infos.sort_keys();
+    }
+    refresh_list_page(state);
+
+    let (name, user, request_id) = (
+        name.to_string(),
+        actor.user.clone(),
+        actor.request_id.clone(),
+    );
+    info!(state.lgr_important.borrow(), "Video uploaded"; "name" => name, "bytes" => size, "user" => user, "request_id" => request_id);
+    Ok(())
+}
+
+/// Runs file I/O on the blocking thread pool, uploads are too large to write on the async workers
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    web::block(f)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?
+}
+
+fn render(
+    state: &State,
+    session: &Session,
+    status: StatusCode,
+    message: Option<String>,
+) -> HttpResponse {
+    let types = UPLOAD_EXTENSIONS.join(" or ");
+    let accept = UPLOAD_EXTENSIONS
+        .iter()
+        .map(|x| format!(".{}", x))
+        .collect::<Vec<_>>()
+        .join(",");
+    let megabytes = UPLOAD_MAX_BYTES / 1_000_000;
+    let html = html! {
+        (DOCTYPE)
+        html {
+            head {
+                (header(state.style_count.load(Ordering::Relaxed)))
+                title { "Upload" }
+            }
+            body {
+                (announcements::markup(state, Target::All))
+                p { "Uploads a " (types) " video of at most " (megabytes) " MB. The name defaults to the name of the file." }
+                form action="upload" method="POST" enctype="multipart/form-data" {
+                    input name="csrf" type="hidden" value=(session.csrf);
+                    input name="name" type="text" placeholder="Name (optional)" size="50";
+                    br;
+                    input name="source" type="text" placeholder="Source (optional)" size="100";
+                    br;
+                    input name="file" type="file" accept=(accept);
+                    br;
+                    input type="submit" value="Upload";
+                }
+                br;
+                a href="/shell" { "Shell" }
+                (logout_form(session))
+                @if let Some(message) = message {
+                    pre class="feedback" { (message) }
+                }
+            }
+        }
+    };
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .body(html.into_string())
+}
@@ -0,0 +1,209 @@
+use {
+    crate::{auth, requestid::RequestId, write_atomically},
+    std::{fmt, fs, io, path::Path, str::FromStr, sync::Mutex},
+};
+
+/// One admin account per line, `<name> <role> <argon2 hash>`, written by `gondola-rs add-user`
+pub static USERS_FILE: &str = "users";
+
+/// Name of the only account when there is no users file and the shared `password` file is used
+pub static PASSWORD_FILE_USER: &str = "admin";
+
+/// What an account may do, every role may do everything the roles before it may
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Announcements only
+    Announcer,
+    /// Sources, visibility, playlists and rescans
+    Editor,
+    /// Everything, including uploads, logs and log levels
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Announcer => "announcer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "announcer" => Ok(Role::Announcer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!(
+                "Unknown role: {}, use announcer, editor or admin",
+                role
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct User {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Who made a change, attached to the log lines of admin actions
+#[derive(Clone, Debug)]
+pub struct Actor {
+    pub user: String,
+    pub request_id: RequestId,
+}
+
+struct Entry {
+    user: User,
+    hash: String,
+}
+
+fn parse(contents: &str) -> Result<Vec<Entry>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(role), Some(hash), None) => Ok(Entry {
+                    user: User {
+                        name: name.to_string(),
+                        role: role.parse()?,
+                    },
+                    hash: hash.to_string(),
+                }),
+                _ => Err(format!(
+                    "Line {} of the users file is not `<name> <role> <hash>`",
+                    number + 1
+                )),
+            }
+        })
+        .collect()
+}
+
+/// The accounts in the users file, `None` when there is no users file
+fn read() -> Result<Option<Vec<Entry>>, String> {
+    match fs::read_to_string(USERS_FILE) {
+        Ok(contents) => parse(&contents).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("Unable to read the users file: {}", err)),
+    }
+}
+
+/// Check a name and password, `Ok(None)` means they are wrong. Without a users file the
+/// `password` file logs in as `PASSWORD_FILE_USER` with the admin role.
+pub fn verify(name: &str, password: &str) -> Result<Option<User>, String> {
+    let Some(entries) = read()? else {
+        let stored = fs::read_to_string("password")
+            .map_err(|err| format!("Unable to read the password file: {}", err))?;
+        let user = User {
+            name: PASSWORD_FILE_USER.to_string(),
+            role: Role::Admin,
+        };
+        return Ok(auth::verify(stored.trim(), password)?.then_some(user));
+    };
+    match entries.into_iter().find(|x| x.user.name == name) {
+        Some(entry) => Ok(auth::verify(&entry.hash, password)?.then_some(entry.user)),
+        None => {
+            // Takes as long as a wrong password, so the time does not tell which users exist
+            auth::verify(&unknown_user_hash(), password)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Hash checked for users that do not exist
+fn unknown_user_hash() -> String {
+    static HASH: Mutex<Option<String>> = Mutex::new(None);
+    HASH.lock()
+        .unwrap()
+        .get_or_insert_with(|| auth::hash("").expect("Hashing an empty password"))
+        .clone()
+}
+
+/// Whether the `password` file is used and still holds a hash from before Argon2
+pub fn legacy_password_file() -> bool {
+    matches!(read(), Ok(None))
+        && fs::read_to_string("password").map_or(false, |x| auth::is_legacy(x.trim()))
+}
+
+/// The current role of an account, so removing or demoting a user applies to their sessions
+pub fn role(name: &str) -> Option<Role> {
+    match read() {
+        Ok(Some(entries)) => entries
+            .into_iter()
+            .find(|x| x.user.name == name)
+            .map(|x| x.user.role),
+        Ok(None) => (name == PASSWORD_FILE_USER).then_some(Role::Admin),
+        Err(_) => None,
+    }
+}
+
+fn write(entries: &[Entry]) -> io::Result<()> {
+    let contents: String = entries
+        .iter()
+        .map(|x| format!("{} {} {}\n", x.user.name, x.user.role, x.hash))
+        .collect();
+    write_atomically(Path::new(USERS_FILE), &contents)
+}
+
+/// `gondola-rs add-user <name> <role>`, replaces the password and role of an existing user
+pub fn add(name: &str, role: &str, password: &str) -> Result<(), String> {
+    if name.is_empty() || name.chars().any(|x| x.is_whitespace() || x.is_control()) {
+        return Err("User names can not be empty or contain spaces".to_string());
+    }
+    let role = role.parse()?;
+    let hash = auth::hash(password)?;
+    let mut entries = read()?.unwrap_or_default();
+    entries.retain(|x| x.user.name != name);
+    entries.push(Entry {
+        user: User {
+            name: name.to_string(),
+            role,
+        },
+        hash,
+    });
+    write(&entries).map_err(|err| format!("Unable to write the users file: {}", err))
+}
+
+/// `gondola-rs remove-user <name>`
+pub fn remove(name: &str) -> Result<(), String> {
+    let mut entries = read()?.unwrap_or_default();
+    let count = entries.len();
+    entries.retain(|x| x.user.name != name);
+    if entries.len() == count {
+        return Err(format!("No such user: {}", name));
+    }
+    write(&entries).map_err(|err| format!("Unable to write the users file: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_users() {
+        let entries =
+            parse("# name role hash\nalice editor $argon2id$x\n\nbob admin $argon2id$y\n").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].user.name, "alice");
+        assert_eq!(entries[0].user.role, Role::Editor);
+        assert_eq!(entries[1].hash, "$argon2id$y");
+        assert!(parse("carol owner $argon2id$z").is_err());
+        assert!(parse("carol admin").is_err());
+        assert!(Role::Admin > Role::Editor && Role::Editor > Role::Announcer);
+    }
+}
@@ -1,12 +1,227 @@
-use file_rotate::{compression::Compression, suffix::AppendCount, ContentLimit, FileRotate};
-use std::io;
-
-pub fn create_rotational_writer(path: &str) -> impl io::Write {
-    FileRotate::new(
-        path,
-        AppendCount::new(3),
-        ContentLimit::Bytes(100_000),
-        Compression::None,
-        None,
-    )
+use crate::config::PERSONAL_DATA_RETENTION;
+use file_rotate::{
+    compression::Compression,
+    suffix::{AppendCount, AppendTimestamp, FileLimit},
+    ContentLimit, FileRotate, TimeFrequency,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Where a log is written to
+#[allow(dead_code)] // The outputs in use are picked in config.rs
+pub enum LogOutput {
+    /// A file that is rotated according to the given settings
+    File(LogFile),
+    /// Standard output, which ends up in journald when running as a systemd service
+    Stdout,
+}
+
+pub struct LogFile {
+    pub rotate: Rotate,
+    pub suffix: Suffix,
+    /// Gzip files when they are rotated
+    pub compress: bool,
+    /// Delete rotated files older than this, never later than `PERSONAL_DATA_RETENTION`
+    pub max_age: Option<Duration>,
+    /// Delete the oldest rotated files when all files of this log exceed this many bytes
+    pub max_total_size: Option<u64>,
+}
+
+#[allow(dead_code)]
+pub enum Rotate {
+    /// Rotate when the file reaches this many bytes
+    Size(usize),
+    Hourly,
+    Daily,
+}
+
+#[allow(dead_code)]
+pub enum Suffix {
+    /// Rotated files are named `log.1`, `log.2`, ..., keeping at most this many
+    Count(usize),
+    /// Rotated files are named `log.20230704T123000`, keeping at most this many
+    Timestamp(usize),
+}
+
+static PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts failed writes instead of returning them, the logger thread stops on the first error
+struct CountErrors<W> {
+    inner: W,
+    errors: Arc<AtomicU64>,
+}
+
+impl<W: io::Write> io::Write for CountErrors<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner.write(buf) {
+            Ok(written) => Ok(written),
+            Err(_) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.inner.flush().is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// A rotating file which also removes rotated files by age and total size
+struct RotatingFile {
+    path: String,
+    config: &'static LogFile,
+    inner: Box<dyn io::Write + Send>,
+    last_prune: Instant,
+}
+
+impl RotatingFile {
+    fn new(path: &str, config: &'static LogFile) -> Self {
+        let mut file = Self {
+            path: path.to_string(),
+            config,
+            inner: Box::new(io::sink()),
+            last_prune: Instant::now(),
+        };
+        file.prune();
+        file.inner = file.open();
+        file
+    }
+
+    fn open(&self) -> Box<dyn io::Write + Send> {
+        let content_limit = match self.config.rotate {
+            Rotate::Size(bytes) => ContentLimit::Bytes(bytes),
+            Rotate::Hourly => ContentLimit::Time(TimeFrequency::Hourly),
+            Rotate::Daily => ContentLimit::Time(TimeFrequency::Daily),
+        };
+        let compression = if self.config.compress {
+            Compression::OnRotate(0)
+        } else {
+            Compression::None
+        };
+        match self.config.suffix {
+            Suffix::Count(files) => Box::new(FileRotate::new(
+                &self.path,
+                AppendCount::new(files),
+                content_limit,
+                compression,
+                None,
+            )),
+            Suffix::Timestamp(files) => Box::new(FileRotate::new(
+                &self.path,
+                AppendTimestamp::default(FileLimit::MaxFiles(files)),
+                content_limit,
+                compression,
+                None,
+            )),
+        }
+    }
+
+    /// Remove rotated files that are too old or too many bytes, returns whether any were removed
+    fn prune(&mut self) -> bool {
+        self.last_prune = Instant::now();
+        let mut removed = false;
+        let mut total: u64 = fs::metadata(&self.path).map(|x| x.len()).unwrap_or(0);
+        let mut rotated: Vec<(PathBuf, SystemTime, u64)> = rotated_files(&self.path)
+            .into_iter()
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                Some((path, metadata.modified().ok()?, metadata.len()))
+            })
+            .collect();
+        total += rotated.iter().map(|x| x.2).sum::<u64>();
+
+        let max_age = self
+            .config
+            .max_age
+            .map_or(PERSONAL_DATA_RETENTION, |x| x.min(PERSONAL_DATA_RETENTION));
+        {
+            let now = SystemTime::now();
+            rotated.retain(|(path, modified, size)| {
+                let too_old = now.duration_since(*modified).unwrap_or_default() > max_age;
+                if too_old && fs::remove_file(path).is_ok() {
+                    total -= size;
+                    removed = true;
+                    return false;
+                }
+                true
+            });
+        }
+
+        if let Some(max_total_size) = self.config.max_total_size {
+            for (path, _, size) in rotated {
+                if total <= max_total_size {
+                    break;
+                }
+                if fs::remove_file(path).is_ok() {
+                    total -= size;
+                    removed = true;
+                }
+            }
+        }
+        removed
+    }
+}
+
+impl io::Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if self.last_prune.elapsed() > PRUNE_INTERVAL && self.prune() {
+            // FileRotate keeps track of the rotated files it has seen, reopen it so it forgets
+            // the removed ones
+            self.inner = self.open();
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Rotated files belonging to the log at `path`, oldest first
+pub fn rotated_files(path: &str) -> Vec<PathBuf> {
+    let path = Path::new(path);
+    let (Some(directory), Some(name)) = (path.parent(), path.file_name()) else {
+        return vec![];
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+                .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+pub fn create_writer(
+    path: &str,
+    output: &'static LogOutput,
+    errors: Arc<AtomicU64>,
+) -> Box<dyn io::Write + Send> {
+    match output {
+        LogOutput::File(config) => Box::new(CountErrors {
+            inner: RotatingFile::new(path, config),
+            errors,
+        }),
+        LogOutput::Stdout => Box::new(CountErrors {
+            inner: io::stdout(),
+            errors,
+        }),
+    }
 }